@@ -1,6 +1,7 @@
 extern crate env_logger;
 extern crate jupyter_client;
 
+use jupyter_client::heartbeat::{HeartbeatEvent, HeartbeatMonitor};
 use jupyter_client::Client;
 
 fn main() {
@@ -8,8 +9,13 @@ fn main() {
 
     let client = Client::existing().expect("creating jupyter connection");
 
-    let receiver = client.heartbeat().unwrap();
-    for msg in receiver.iter().take(5) {
-        println!("{:?}", msg);
+    let receiver = client
+        .monitor_heartbeat(HeartbeatMonitor::new())
+        .expect("monitoring heartbeat");
+    for event in receiver.iter().take(5) {
+        println!("{:?}", event);
+        if event == HeartbeatEvent::Dead {
+            eprintln!("kernel is dead");
+        }
     }
 }
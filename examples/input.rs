@@ -0,0 +1,29 @@
+extern crate env_logger;
+extern crate jupyter_client;
+
+use jupyter_client::commands::Command;
+use jupyter_client::stdin::ScriptedResponder;
+use jupyter_client::Client;
+use std::collections::HashMap;
+
+fn main() {
+    env_logger::init();
+
+    let client = Client::existing().expect("creating jupyter connection");
+
+    let responder = ScriptedResponder::new(vec!["Simon"]);
+    client
+        .set_input_handler(responder)
+        .expect("setting input handler");
+
+    let command = Command::Execute {
+        code: "name = input('Name: ')\nprint('Hello', name)".to_string(),
+        silent: false,
+        store_history: true,
+        user_expressions: HashMap::new(),
+        allow_stdin: true,
+        stop_on_error: false,
+    };
+    let response = client.send_shell_command(command).expect("sending command");
+    println!("Response: {:#?}", response);
+}
@@ -18,7 +18,7 @@ fn main() {
 "#
     .to_string();
     let prep_cmd = Command::Execute {
-        code: code,
+        code,
         silent: false,
         store_history: true,
         user_expressions: HashMap::new(),
@@ -0,0 +1,267 @@
+/*! Asynchronous client, available with the `async` feature.
+
+Requests are routed by the same background threads as the blocking [`Client`](struct.Client.html),
+so awaiting a reply does not block a tokio worker thread.
+*/
+use crate::client::Client;
+use crate::commands::Command;
+use crate::errors::{Error, Result};
+use crate::iopub::{IoPubSubscription, StopListening};
+use crate::responses::Response;
+use crate::router::Router;
+use futures_core::Stream;
+use log::debug;
+use std::io::Read;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+
+/** Asynchronous counterpart of [`Client`](struct.Client.html).
+
+Request methods are `async fn`s returning the same [`Response`](responses/enum.Response.html)
+types, and IOPub messages are delivered as a [`Stream`][stream].
+
+```no_run
+# use jupyter_client::{AsyncClient, Result};
+use jupyter_client::commands::Command;
+
+# async fn run() -> Result<()> {
+let client = AsyncClient::existing()?;
+let response = client.send_shell_command(Command::KernelInfo).await?;
+println!("{:?}", response);
+# Ok(())
+# }
+```
+
+The underlying blocking client, for example to change the session or default timeout, is
+available with [`client`](#method.client) and [`client_mut`](#method.client_mut).
+
+[stream]: https://docs.rs/futures-core/0.3/futures_core/stream/trait.Stream.html
+*/
+pub struct AsyncClient {
+    client: Client,
+}
+
+impl AsyncClient {
+    /// Connect to the latest existing connection info file, as with
+    /// [`Client::existing`](struct.Client.html#method.existing).
+    pub fn existing() -> Result<Self> {
+        Client::existing().map(AsyncClient::from)
+    }
+
+    /// Connect to a kernel with a definition from a connection info reader, as with
+    /// [`Client::from_reader`](struct.Client.html#method.from_reader).
+    pub fn from_reader<R>(reader: R) -> Result<Self>
+    where
+        R: Read,
+    {
+        Client::from_reader(reader).map(AsyncClient::from)
+    }
+
+    /// The underlying blocking client.
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    /// The underlying blocking client, mutably.
+    pub fn client_mut(&mut self) -> &mut Client {
+        &mut self.client
+    }
+
+    /// Send a shell command to the kernel, waiting at most the client's default timeout.
+    pub async fn send_shell_command(&self, command: Command) -> Result<Response> {
+        debug!("Sending shell command: {:?}", command);
+        let timeout = self.client.timeout();
+        self.request(command, self.client.shell_router(), timeout)
+            .await
+    }
+
+    /// Send a shell command to the kernel, waiting at most `timeout` for the reply.
+    pub async fn send_shell_command_timeout(
+        &self,
+        command: Command,
+        timeout: Duration,
+    ) -> Result<Response> {
+        debug!("Sending shell command: {:?}", command);
+        self.request(command, self.client.shell_router(), Some(timeout))
+            .await
+    }
+
+    /// Send a control command to the kernel, waiting at most the client's default timeout.
+    pub async fn send_control_command(&self, command: Command) -> Result<Response> {
+        debug!("Sending control command: {:?}", command);
+        let timeout = self.client.timeout();
+        self.request(command, self.client.control_router(), timeout)
+            .await
+    }
+
+    /// Send a control command to the kernel, waiting at most `timeout` for the reply.
+    pub async fn send_control_command_timeout(
+        &self,
+        command: Command,
+        timeout: Duration,
+    ) -> Result<Response> {
+        debug!("Sending control command: {:?}", command);
+        self.request(command, self.client.control_router(), Some(timeout))
+            .await
+    }
+
+    /// Send a comm command to the kernel. This does not wait for anything, as comm messages get
+    /// no reply.
+    pub fn send_comm_command(&self, command: Command) -> Result<()> {
+        self.client.send_comm_command(command)
+    }
+
+    /** Subscribe to IOPub messages.
+
+    Messages that cannot be received or parsed are delivered as errors, and the stream carries
+    on with the next message.
+    */
+    pub fn iopub_subscribe(&self) -> Result<IoPubStream> {
+        self.iopub_subscribe_with(IoPubSubscription::new())
+    }
+
+    /// Subscribe to the IOPub messages matching `subscription`, as with
+    /// [`Client::iopub_subscribe_with`](struct.Client.html#method.iopub_subscribe_with).
+    pub fn iopub_subscribe_with(&self, subscription: IoPubSubscription) -> Result<IoPubStream> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let listening = self.client.subscribe_iopub(
+            subscription,
+            Box::new(move |msg| tx.send(msg.clone()).is_ok()),
+        );
+        Ok(IoPubStream {
+            rx,
+            _listening: listening,
+        })
+    }
+
+    async fn request(
+        &self,
+        command: Command,
+        router: &Router,
+        timeout: Option<Duration>,
+    ) -> Result<Response> {
+        let wire = self.client.wire(command)?;
+        let (tx, rx) = oneshot::channel();
+        // Dropping this future drops the guard, so an abandoned request is forgotten.
+        let _request = router.start(
+            wire,
+            Box::new(move |reply| {
+                let _ = tx.send(reply);
+            }),
+        )?;
+
+        let reply = match timeout {
+            Some(timeout) => tokio::time::timeout(timeout, rx)
+                .await
+                .map_err(|_| Error::Timeout(timeout))?,
+            None => rx.await,
+        };
+        reply.map_err(|_| Error::KernelDead)?
+    }
+}
+
+impl From<Client> for AsyncClient {
+    fn from(client: Client) -> Self {
+        AsyncClient { client }
+    }
+}
+
+/// Stream of IOPub messages from the kernel.
+#[derive(Debug)]
+pub struct IoPubStream {
+    rx: mpsc::UnboundedReceiver<Result<Response>>,
+    _listening: StopListening,
+}
+
+impl Stream for IoPubStream {
+    type Item = Result<Response>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.rx.poll_recv(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::responses::{IoPubResponse, IsCompleteStatus, ShellResponse};
+    use crate::test_helpers::FakeKernel;
+    use serde_json::json;
+    use std::future::poll_fn;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+
+    #[tokio::test]
+    async fn test_async_request() {
+        let kernel = FakeKernel::new();
+        let client = AsyncClient::from(kernel.client());
+
+        let handle = thread::spawn(move || {
+            let request = kernel.recv(&kernel.shell);
+            kernel.reply(
+                &kernel.shell,
+                &request,
+                "is_complete_reply",
+                json!({"status": "complete"}),
+            );
+            kernel
+        });
+        let command = Command::IsComplete {
+            code: "1 + 1".to_string(),
+        };
+        match client
+            .send_shell_command_timeout(command, Duration::from_secs(5))
+            .await
+        {
+            Ok(Response::Shell(ShellResponse::IsComplete { content, .. })) => {
+                assert_eq!(content, IsCompleteStatus::Complete)
+            }
+            other => unreachable!("expected an is_complete reply, got {:?}", other),
+        }
+        let kernel = handle.join().unwrap();
+
+        // Nothing replies to this one
+        let command = Command::IsComplete {
+            code: "1 + 1".to_string(),
+        };
+        let timeout = Duration::from_millis(50);
+        match client.send_shell_command_timeout(command, timeout).await {
+            Err(Error::Timeout(t)) => assert_eq!(t, timeout),
+            other => unreachable!("expected a timeout, got {:?}", other),
+        }
+        drop(kernel);
+    }
+
+    #[tokio::test]
+    async fn test_iopub_stream() {
+        let kernel = FakeKernel::new();
+        let client = AsyncClient::from(kernel.client());
+        let mut stream = client.iopub_subscribe().unwrap();
+
+        // The subscription takes a moment to reach the publisher, so keep publishing until the
+        // stream yields a message
+        let done = Arc::new(AtomicBool::new(false));
+        let publisher = {
+            let done = done.clone();
+            thread::spawn(move || {
+                while !done.load(Ordering::SeqCst) {
+                    kernel.publish("status", json!({"execution_state": "idle"}));
+                    thread::sleep(Duration::from_millis(10));
+                }
+            })
+        };
+        let msg = poll_fn(|cx| Pin::new(&mut stream).poll_next(cx))
+            .await
+            .unwrap()
+            .unwrap();
+        match msg {
+            Response::IoPub(IoPubResponse::Status { .. }) => {}
+            other => unreachable!("expected a status message, got {:?}", other),
+        }
+        done.store(true, Ordering::SeqCst);
+        publisher.join().unwrap();
+    }
+}
@@ -0,0 +1,61 @@
+/*! Plumbing shared by the background threads that own the kernel's sockets.
+
+Each thread polls its kernel socket together with one end of an inproc PAIR socket. The other
+end is used to hand it work, or the `SHUTDOWN` frame asking it to stop.
+*/
+use crate::errors::{Error, Result};
+use std::sync::{Mutex, MutexGuard};
+
+/// Frame asking a background thread to stop. Real messages always have several frames.
+pub(crate) static SHUTDOWN: &[u8] = b"";
+
+/// Connected inproc sockets, returned as the end for the background thread and the end used to
+/// talk to it.
+pub(crate) fn pair(ctx: &zmq::Context, name: &str) -> Result<(zmq::Socket, zmq::Socket)> {
+    let endpoint = format!("inproc://{}-{}", name, uuid::Uuid::new_v4());
+    let thread_end = ctx.socket(zmq::PAIR)?;
+    thread_end.set_rcvhwm(0)?;
+    thread_end.bind(&endpoint)?;
+    let caller_end = ctx.socket(zmq::PAIR)?;
+    // No limit, so a failed send can only mean the thread has stopped
+    caller_end.set_sndhwm(0)?;
+    caller_end.connect(&endpoint)?;
+    Ok((thread_end, caller_end))
+}
+
+/** Wait until `socket` or the thread end `incoming` can be read, and return which can.
+
+Polls interrupted by a signal are retried, so only real errors stop the thread.
+*/
+pub(crate) fn poll(socket: &zmq::Socket, incoming: &zmq::Socket) -> zmq::Result<(bool, bool)> {
+    loop {
+        let mut items = [
+            socket.as_poll_item(zmq::POLLIN),
+            incoming.as_poll_item(zmq::POLLIN),
+        ];
+        match zmq::poll(&mut items, -1) {
+            Ok(_) => return Ok((items[0].is_readable(), items[1].is_readable())),
+            Err(zmq::Error::EINTR) => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Hand frames to a background thread through the caller end of its sockets. This never
+/// blocks, and fails with `Error::KernelDead` once the thread has stopped.
+pub(crate) fn send<I, T>(caller_end: &zmq::Socket, frames: I) -> Result<()>
+where
+    I: IntoIterator<Item = T>,
+    T: Into<zmq::Message>,
+{
+    caller_end
+        .send_multipart(frames, zmq::DONTWAIT)
+        .map_err(|e| match e {
+            zmq::Error::EAGAIN => Error::KernelDead,
+            e => e.into(),
+        })
+}
+
+pub(crate) fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
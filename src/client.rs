@@ -1,34 +1,44 @@
 use crate::commands::Command;
 use crate::connection_config::ConnectionConfig;
-use crate::errors::Result;
+use crate::errors::{Error, Result};
+use crate::execution::{ExecutionHandle, ExecutionOutcome};
+use crate::header::Session;
+use crate::heartbeat::{HeartbeatEvent, HeartbeatMonitor};
+use crate::iopub::{IoPubDispatcher, IoPubReceiver, IoPubSubscription, StopListening};
+use crate::kernelspec::InterruptMode;
 use crate::paths::jupyter_runtime_dir;
 use crate::responses::Response;
-use crate::signatures::HmacSha256;
-use failure::format_err;
+use crate::signatures::Auth;
+use crate::stdin::{InputHandler, StdinChannel};
+use crate::wire::WireMessage;
 use glob::glob;
-use hmac::Mac;
 use log::{debug, trace};
+use std::collections::HashMap;
 use std::env::current_dir;
 use std::fs;
 use std::io::Read;
 use std::path::PathBuf;
 use std::sync::mpsc::{self, Receiver};
-use std::sync::{Arc, Mutex};
-use std::thread;
+use std::sync::Arc;
 use std::time::Duration;
 
+use crate::router::Router;
 use crate::socket::Socket;
 
-fn find_connection_file<S>(glob_pattern: S, paths: Option<Vec<PathBuf>>) -> Option<PathBuf>
+/// How long to wait for an `interrupt_reply` when the client has no default timeout.
+const INTERRUPT_TIMEOUT: Duration = Duration::from_secs(5);
+
+fn find_connection_file<S>(glob_pattern: S, paths: Option<Vec<PathBuf>>) -> Result<Option<PathBuf>>
 where
     S: Into<String>,
 {
-    let paths = paths.unwrap_or_else(|| {
-        vec![
+    let paths = match paths {
+        Some(paths) => paths,
+        None => vec![
             current_dir().unwrap_or_else(|_| PathBuf::from(".")),
-            jupyter_runtime_dir(),
-        ]
-    });
+            jupyter_runtime_dir()?,
+        ],
+    };
     trace!("connection file paths to search: {:?}", paths);
 
     let glob_pattern = glob_pattern.into();
@@ -45,10 +55,10 @@ where
                 metadata.modified().unwrap()
             });
             trace!("sorted matches: {:#?}", matches);
-            return Some(matches.last().unwrap().clone());
+            return Ok(Some(matches.last().unwrap().clone()));
         }
     }
-    None
+    Ok(None)
 }
 
 /** The main `Client` struct.
@@ -65,9 +75,38 @@ conversion of messages to and from the [on-the-wire format][wire-format].
 
 - [`send_shell_command`][send-shell-command]: send a shell command (like running a cell's contents)
 - [`send_control_command`][send-control-command]: send an important shell command
+- [`send_comm_command`][send-comm-command]: send a comm message, which gets no reply
+- [`execute`][execute]: run code, and wait for its reply and all of its output
+- [`start_execute`][start-execute]: run code, and follow its output as it arrives
+- [`send_shell_command_timeout`][send-shell-command-timeout] and
+  [`send_control_command_timeout`][send-control-command-timeout]: give up waiting for a reply
+  after a timeout
 - [`iopub_subscribe`][iopub-subscribe]: subscribe to published information from the kernel
+- [`iopub_subscribe_with`][iopub-subscribe-with]: subscribe to some of the published messages,
+  by type, parent request or topic
 - [`heartbeat_every`][heartbeat-every]: control the heartbeat and find out if the kernel dies
 - [`heartbeat`][heartbeat]: send a heartbeat every second
+- [`monitor_heartbeat`][monitor-heartbeat]: find out if the kernel dies, with configurable timeouts
+- [`set_input_handler`][set-input-handler]: answer input requests from the kernel
+- [`interrupt`][interrupt]: interrupt the currently running code
+
+## Timeouts
+
+By default, shell and control commands wait for a reply forever. A default timeout for all
+commands can be set with [`set_timeout`][set-timeout]. When a command times out, its reply is
+dropped if it arrives later, and the client can carry on sending commands.
+
+## Concurrent requests
+
+Replies are matched to requests by message id, so commands can be sent from several threads at
+once. For example, a `complete_request` can be answered while a long `execute_request` is still
+running.
+
+## Session identity
+
+Every message sent by a client carries the same [session id][session-id] and
+[username][username] in its header, so the kernel and other frontends can tell clients apart.
+[`is_own_message`][is-own-message] checks whether a message was caused by this client.
 
 
 [wire-format]: https://jupyter-client.readthedocs.io/en/stable/messaging.html#the-wire-protocol
@@ -75,16 +114,35 @@ conversion of messages to and from the [on-the-wire format][wire-format].
 [from_reader]: #method.from_reader
 [send-shell-command]: #method.send_shell_command
 [send-control-command]: #method.send_control_command
+[send-comm-command]: #method.send_comm_command
+[execute]: #method.execute
+[start-execute]: #method.start_execute
+[send-shell-command-timeout]: #method.send_shell_command_timeout
+[send-control-command-timeout]: #method.send_control_command_timeout
+[set-timeout]: #method.set_timeout
 [iopub-subscribe]: #method.iopub_subscribe
+[iopub-subscribe-with]: #method.iopub_subscribe_with
 [heartbeat-every]: #method.heartbeat_every
 [heartbeat]: #method.heartbeat
+[monitor-heartbeat]: #method.monitor_heartbeat
+[set-input-handler]: #method.set_input_handler
+[interrupt]: #method.interrupt
+[session-id]: #method.session_id
+[username]: #method.username
+[is-own-message]: #method.is_own_message
 */
 pub struct Client {
-    shell_socket: Socket,
-    control_socket: Socket,
-    iopub_socket: Arc<Mutex<Socket>>,
-    heartbeat_socket: Arc<Mutex<Socket>>,
-    auth: HmacSha256,
+    shell: Router,
+    control: Router,
+    stdin: StdinChannel,
+    iopub: IoPubDispatcher,
+    ctx: zmq::Context,
+    config: Arc<ConnectionConfig>,
+    auth: Auth,
+    session: Session,
+    interrupt_mode: InterruptMode,
+    kernel_pid: Option<u32>,
+    timeout: Option<Duration>,
 }
 
 impl Client {
@@ -104,8 +162,8 @@ impl Client {
     pub fn existing() -> Result<Self> {
         use std::fs::File;
 
-        find_connection_file("kernel-*.json", None)
-            .ok_or_else(|| format_err!("no connection file found"))
+        find_connection_file("kernel-*.json", None)?
+            .ok_or_else(|| Error::Config("no connection file found".to_string()))
             .and_then(|filename| {
                 debug!("found connection file {:?}", filename);
                 let f = File::open(filename)?;
@@ -135,75 +193,254 @@ impl Client {
         R: Read,
     {
         let config: ConnectionConfig = ConnectionConfig::from_reader(reader)?;
-        let auth = HmacSha256::new_varkey(config.key.as_bytes())
-            .map_err(|e| format_err!("Error constructing HMAC: {:?}", e))?;
+        let auth = Auth::new(&config.signature_scheme, config.key.as_bytes())?;
 
         let ctx = zmq::Context::new();
 
-        let shell_socket = Socket::new_shell(&ctx, &config)?;
+        // The shell and stdin sockets share an identity so the kernel can route input requests
+        // back to us.
+        let identity = uuid::Uuid::new_v4().to_string().into_bytes();
+        let shell_socket = Socket::new_shell(&ctx, &config, &identity)?;
         let control_socket = Socket::new_control(&ctx, &config)?;
+        let stdin_socket = Socket::new_stdin(&ctx, &config, &identity)?;
         let iopub_socket = Socket::new_iopub(&ctx, &config)?;
-        let heartbeat_socket = Socket::new_heartbeat(&ctx, &config)?;
 
         Ok(Client {
-            shell_socket,
-            control_socket,
-            iopub_socket: Arc::new(Mutex::new(iopub_socket)),
-            heartbeat_socket: Arc::new(Mutex::new(heartbeat_socket)),
-            auth: auth,
+            shell: Router::new(&ctx, shell_socket, auth.clone())?,
+            control: Router::new(&ctx, control_socket, auth.clone())?,
+            stdin: StdinChannel::new(&ctx, stdin_socket, auth.clone())?,
+            iopub: IoPubDispatcher::new(&ctx, iopub_socket, auth.clone())?,
+            auth,
+            session: Session::default(),
+            interrupt_mode: InterruptMode::default(),
+            kernel_pid: None,
+            timeout: None,
+            ctx,
+            config: Arc::new(config),
         })
     }
 
     /** Send a shell command to the kernel.
+
+    This waits for the reply for at most the client's [default timeout](#method.set_timeout).
      */
     pub fn send_shell_command(&self, command: Command) -> Result<Response> {
         debug!("Sending shell command: {:?}", command);
-        self.send_command_to_socket(command, &self.shell_socket)
+        self.send_command_to_socket(command, &self.shell, self.timeout)
+    }
+
+    /** Send a shell command to the kernel, waiting at most `timeout` for the reply.
+
+    Returns `Error::Timeout` if no reply arrives in time.
+    */
+    pub fn send_shell_command_timeout(
+        &self,
+        command: Command,
+        timeout: Duration,
+    ) -> Result<Response> {
+        debug!("Sending shell command: {:?}", command);
+        self.send_command_to_socket(command, &self.shell, Some(timeout))
     }
 
     /** Send a control command to the kernel.
+
+    This waits for the reply for at most the client's [default timeout](#method.set_timeout).
      */
     pub fn send_control_command(&self, command: Command) -> Result<Response> {
         debug!("Sending control command: {:?}", command);
-        self.send_command_to_socket(command, &self.control_socket)
+        self.send_command_to_socket(command, &self.control, self.timeout)
     }
 
-    fn send_command_to_socket(&self, command: Command, socket: &Socket) -> Result<Response> {
-        let wire = command.into_wire(self.auth.clone())?;
-        socket.send_wire(wire)?;
-        let resp_wire = socket.recv_wire(self.auth.clone())?;
-        resp_wire.into_response()
+    /** Send a control command to the kernel, waiting at most `timeout` for the reply.
+
+    Returns `Error::Timeout` if no reply arrives in time.
+    */
+    pub fn send_control_command_timeout(
+        &self,
+        command: Command,
+        timeout: Duration,
+    ) -> Result<Response> {
+        debug!("Sending control command: {:?}", command);
+        self.send_command_to_socket(command, &self.control, Some(timeout))
+    }
+
+    /// Default timeout for shell and control commands, or `None` to wait forever.
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    /// Set the default timeout for shell and control commands, or `None` to wait forever.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /** Send a comm command to the kernel.
+
+    Comm messages are sent on the shell channel but the kernel does not reply to them, so this
+    returns as soon as the message is sent. Any response arrives on IOPub.
+    */
+    pub fn send_comm_command(&self, command: Command) -> Result<()> {
+        match command {
+            Command::CommOpen { .. } | Command::CommMsg { .. } | Command::CommClose { .. } => {
+                debug!("Sending comm command: {:?}", command);
+                let wire = command.into_wire(self.auth.clone(), &self.session)?;
+                self.shell.send(wire)
+            }
+            _ => Err(Error::InvalidOperation(format!(
+                "not a comm command: {:?}",
+                command
+            ))),
+        }
+    }
+
+    fn send_command_to_socket(
+        &self,
+        command: Command,
+        router: &Router,
+        timeout: Option<Duration>,
+    ) -> Result<Response> {
+        let wire = self.wire(command)?;
+        router.request(wire, timeout)
+    }
+
+    /// Build the wire message for a command, signed and stamped with this client's session.
+    pub(crate) fn wire(&self, command: Command) -> Result<WireMessage<Auth>> {
+        command.into_wire(self.auth.clone(), &self.session)
+    }
+
+    pub(crate) fn shell_router(&self) -> &Router {
+        &self.shell
+    }
+
+    #[cfg(feature = "async")]
+    pub(crate) fn control_router(&self) -> &Router {
+        &self.control
     }
 
     /** Subscribe to IOPub messages.
+
+    Every subscriber receives each message published from now on. Messages that cannot be
+    received or parsed are delivered as errors, and the channel carries on with the next message.
+    The channel is closed when the client is dropped, and dropping the receiver unsubscribes it.
      */
-    pub fn iopub_subscribe(&self) -> Result<Receiver<Response>> {
+    pub fn iopub_subscribe(&self) -> Result<IoPubReceiver> {
+        self.iopub_subscribe_with(IoPubSubscription::new())
+    }
+
+    /** Subscribe to the IOPub messages matching `subscription`.
+
+    This is like [`iopub_subscribe`](#method.iopub_subscribe), but messages not matching the
+    subscription are dropped without being parsed, as described in the
+    [`iopub`](iopub/index.html) module.
+    */
+    pub fn iopub_subscribe_with(&self, subscription: IoPubSubscription) -> Result<IoPubReceiver> {
         let (tx, rx) = mpsc::channel();
-        let socket = self.iopub_socket.clone();
-        let auth = self.auth.clone();
-
-        thread::spawn(move || loop {
-            let socket = socket.lock().unwrap();
-            let wire = socket.recv_wire(auth.clone()).unwrap();
-            let msg = wire.into_response().unwrap();
-            tx.send(msg).unwrap();
-        });
+        let listening = self.iopub.subscribe(
+            subscription,
+            Box::new(move |msg| tx.send(msg.clone()).is_ok()),
+        );
+        Ok(IoPubReceiver::new(rx, listening))
+    }
+
+    /// Pass every IOPub message received from now on that matches `subscription` to
+    /// `listener`, until it returns `false` or the returned guard is dropped.
+    pub(crate) fn subscribe_iopub(
+        &self,
+        subscription: IoPubSubscription,
+        listener: crate::iopub::Listener,
+    ) -> StopListening {
+        self.iopub.subscribe(subscription, listener)
+    }
 
+    /** Run code on the kernel, and collect its output.
+
+    This sends an `execute_request` with the default options, and waits until the kernel has
+    replied and gone back to `idle`. Input requests are not allowed, as there is nobody to
+    answer them. The client's [default timeout](#method.set_timeout) applies to the whole
+    execution.
+    */
+    pub fn execute<S>(&self, code: S) -> Result<ExecutionOutcome>
+    where
+        S: Into<String>,
+    {
+        self.start_execute(code)?.wait()
+    }
+
+    /** Send an execute command, and collect its output.
+
+    This is like [`execute`](#method.execute), but takes a full `Command::Execute` to control
+    the options sent to the kernel.
+    */
+    pub fn execute_command(&self, command: Command) -> Result<ExecutionOutcome> {
+        self.start_execute_command(command)?.wait()
+    }
+
+    /** Start running code on the kernel, without waiting for it to finish.
+
+    The returned [`ExecutionHandle`](execution/struct.ExecutionHandle.html) yields the
+    request's output as soon as it is published, and can [`wait`][wait] for the rest or
+    [`interrupt`][interrupt] the kernel. The options are the same as for
+    [`execute`](#method.execute).
+
+    [wait]: execution/struct.ExecutionHandle.html#method.wait
+    [interrupt]: execution/struct.ExecutionHandle.html#method.interrupt
+    */
+    pub fn start_execute<S>(&self, code: S) -> Result<ExecutionHandle<'_>>
+    where
+        S: Into<String>,
+    {
+        self.start_execute_command(Command::Execute {
+            code: code.into(),
+            silent: false,
+            store_history: true,
+            user_expressions: HashMap::new(),
+            allow_stdin: false,
+            stop_on_error: true,
+        })
+    }
+
+    /// Start an execute command, without waiting for it to finish. This is like
+    /// [`start_execute`](#method.start_execute), but takes a full `Command::Execute`.
+    pub fn start_execute_command(&self, command: Command) -> Result<ExecutionHandle<'_>> {
+        if !matches!(command, Command::Execute { .. }) {
+            return Err(Error::InvalidOperation(format!(
+                "not an execute command: {:?}",
+                command
+            )));
+        }
+        debug!("Executing: {:?}", command);
+        ExecutionHandle::start(self, self.wire(command)?)
+    }
+
+    /** Monitor the kernel heartbeat.
+
+    Each heartbeat is reported on the returned channel, as described in the
+    [`heartbeat`](heartbeat/index.html) module. The channel is closed after the kernel is
+    reported dead, and monitoring stops when the receiver is dropped.
+    */
+    pub fn monitor_heartbeat(&self, monitor: HeartbeatMonitor) -> Result<Receiver<HeartbeatEvent>> {
+        let (tx, rx) = mpsc::channel();
+        monitor.spawn(self.heartbeat_connector(), move |event| {
+            tx.send(event).is_ok()
+        })?;
         Ok(rx)
     }
 
     /** Subscribe to heartbeat messages on a given duration.
+
+    A message is sent each time the kernel replies. The channel is closed once the kernel is
+    considered dead, with the default [`HeartbeatMonitor`](heartbeat/struct.HeartbeatMonitor.html)
+    settings; use [`monitor_heartbeat`](#method.monitor_heartbeat) for more control.
      */
     pub fn heartbeat_every(&self, seconds: Duration) -> Result<Receiver<()>> {
         let (tx, rx) = mpsc::channel();
-        let socket = self.heartbeat_socket.clone();
-
-        thread::spawn(move || loop {
-            let socket = socket.lock().unwrap();
-            let _msg = socket.heartbeat().unwrap();
-            tx.send(()).unwrap();
-            thread::sleep(seconds);
-        });
+        HeartbeatMonitor::new().interval(seconds).spawn(
+            self.heartbeat_connector(),
+            move |event| match event {
+                HeartbeatEvent::Alive(_) => tx.send(()).is_ok(),
+                _ => true,
+            },
+        )?;
         Ok(rx)
     }
 
@@ -212,4 +449,428 @@ impl Client {
     pub fn heartbeat(&self) -> Result<Receiver<()>> {
         self.heartbeat_every(Duration::from_secs(1))
     }
+
+    fn heartbeat_connector(&self) -> impl Fn() -> Result<Socket> + Send + 'static {
+        let ctx = self.ctx.clone();
+        let config = self.config.clone();
+        move || Socket::new_heartbeat(&ctx, &config)
+    }
+
+    /** Answer input requests from the kernel with the given handler.
+
+    A background thread listens on the stdin channel and replies to each `input_request` with
+    the value returned from the handler. Setting another handler replaces this one. Commands
+    must be sent with `allow_stdin: true` for the kernel to send input requests.
+
+    Fails with `Error::KernelDead` if the background thread has stopped after a socket error.
+    */
+    pub fn set_input_handler<H>(&self, handler: H) -> Result<()>
+    where
+        H: InputHandler + 'static,
+    {
+        self.stdin
+            .set_handler(Box::new(handler), self.session.clone())
+    }
+
+    /// The session id stamped on every message sent by this client.
+    pub fn session_id(&self) -> &str {
+        &self.session.id
+    }
+
+    /** Override the session id stamped on every message sent by this client.
+
+    By default each client generates a random UUID. Input handlers that are already set
+    keep using the previous session id.
+    */
+    pub fn set_session_id<S>(&mut self, session_id: S)
+    where
+        S: Into<String>,
+    {
+        self.session.id = session_id.into();
+    }
+
+    /// The username stamped on every message sent by this client.
+    pub fn username(&self) -> &str {
+        &self.session.username
+    }
+
+    /** Set the username stamped on every message sent by this client.
+
+    The default is `client`.
+    */
+    pub fn set_username<S>(&mut self, username: S)
+    where
+        S: Into<String>,
+    {
+        self.session.username = username.into();
+    }
+
+    /** Whether a message was caused by a request from this client.
+
+    This compares the session in the message's parent header with this client's session id, and
+    is useful to filter IOPub messages caused by other frontends connected to the same kernel.
+    */
+    pub fn is_own_message(&self, response: &Response) -> bool {
+        response
+            .parent_header()
+            .map(|parent| parent.session == self.session.id)
+            .unwrap_or(false)
+    }
+
+    /** Set how the kernel should be interrupted.
+
+    This should match the `interrupt_mode` of the kernel's kernelspec. The default is
+    [`InterruptMode::Signal`](kernelspec/enum.InterruptMode.html#variant.Signal).
+    */
+    pub fn set_interrupt_mode(&mut self, mode: InterruptMode) {
+        self.interrupt_mode = mode;
+    }
+
+    /** Set the process id of the kernel.
+
+    This is required to interrupt kernels with an `interrupt_mode` of `signal`. Clients from a
+    [`KernelManager`](struct.KernelManager.html) know the process id of their kernel, but those
+    built from a connection file do not.
+    */
+    pub fn set_kernel_pid(&mut self, pid: u32) {
+        self.kernel_pid = Some(pid);
+    }
+
+    /** Interrupt the kernel.
+
+    Depending on the [interrupt mode](#method.set_interrupt_mode), this either sends an
+    `interrupt_request` on the control channel, or sends `SIGINT` to the kernel process.
+
+    An `interrupt_reply` is awaited for at most the client's [default timeout](#method.set_timeout),
+    or 5 seconds if it has none, so a hung kernel returns `Error::Timeout`.
+
+    Sending `SIGINT` requires the [kernel pid](#method.set_kernel_pid), which is only known to
+    clients from a [`KernelManager`](struct.KernelManager.html). A client built from a connection
+    file, e.g. with [`existing`](#method.existing), returns `Error::InvalidOperation` unless the
+    pid is set by hand.
+    */
+    pub fn interrupt(&self) -> Result<()> {
+        match self.interrupt_mode {
+            InterruptMode::Message => {
+                let timeout = self.timeout.unwrap_or(INTERRUPT_TIMEOUT);
+                self.send_control_command_timeout(Command::Interrupt, timeout)?;
+                Ok(())
+            }
+            InterruptMode::Signal => {
+                let pid = self.kernel_pid.ok_or_else(|| {
+                    Error::InvalidOperation(
+                        "cannot signal kernel: process id not known".to_string(),
+                    )
+                })?;
+                debug!("sending SIGINT to kernel process {}", pid);
+                send_sigint(pid)
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+fn send_sigint(pid: u32) -> Result<()> {
+    let ret = unsafe { libc::kill(pid as libc::pid_t, libc::SIGINT) };
+    if ret != 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn send_sigint(_pid: u32) -> Result<()> {
+    Err(Error::InvalidOperation(
+        "signal interrupts are not supported on this platform".to_string(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::execution::Output;
+    use crate::responses::{IsCompleteStatus, ShellResponse, Status};
+    use crate::test_helpers::FakeKernel;
+    use serde_json::json;
+    use std::thread;
+
+    fn is_complete() -> Command {
+        Command::IsComplete {
+            code: "1 + 1".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_concurrent_requests() {
+        let kernel = FakeKernel::new();
+        let client = kernel.client();
+
+        // Answer both requests in the opposite order to which they arrived, with a status that
+        // depends on the code sent
+        let handle = thread::spawn(move || {
+            let first = kernel.recv(&kernel.shell);
+            let second = kernel.recv(&kernel.shell);
+            for request in &[second, first] {
+                let content: serde_json::Value = serde_json::from_slice(&request.content).unwrap();
+                let status = if content["code"] == "complete" {
+                    "complete"
+                } else {
+                    "invalid"
+                };
+                kernel.reply(
+                    &kernel.shell,
+                    request,
+                    "is_complete_reply",
+                    json!({ "status": status }),
+                );
+            }
+        });
+
+        let send = |code: &str| {
+            let command = Command::IsComplete {
+                code: code.to_string(),
+            };
+            match client.send_shell_command_timeout(command, Duration::from_secs(5)) {
+                Ok(Response::Shell(ShellResponse::IsComplete { content, .. })) => content,
+                other => unreachable!("expected an is_complete reply, got {:?}", other),
+            }
+        };
+        thread::scope(|s| {
+            let complete = s.spawn(|| send("complete"));
+            let invalid = s.spawn(|| send("invalid"));
+            assert_eq!(complete.join().unwrap(), IsCompleteStatus::Complete);
+            assert_eq!(invalid.join().unwrap(), IsCompleteStatus::Invalid);
+        });
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_client_usable_after_timeout() {
+        let kernel = FakeKernel::new();
+        let client = kernel.client();
+
+        let timeout = Duration::from_millis(100);
+        match client.send_shell_command_timeout(is_complete(), timeout) {
+            Err(Error::Timeout(t)) => assert_eq!(t, timeout),
+            other => unreachable!("expected a timeout, got {:?}", other),
+        }
+
+        // Reply to the first request only after the second one is sent, so the late reply must
+        // be ignored
+        let handle = thread::spawn(move || {
+            let late = kernel.recv(&kernel.shell);
+            let request = kernel.recv(&kernel.shell);
+            kernel.reply(
+                &kernel.shell,
+                &late,
+                "is_complete_reply",
+                json!({"status": "invalid"}),
+            );
+            kernel.reply(
+                &kernel.shell,
+                &request,
+                "is_complete_reply",
+                json!({"status": "complete"}),
+            );
+        });
+        match client.send_shell_command_timeout(is_complete(), Duration::from_secs(5)) {
+            Ok(Response::Shell(ShellResponse::IsComplete { content, .. })) => {
+                assert_eq!(content, IsCompleteStatus::Complete)
+            }
+            other => unreachable!("expected an is_complete reply, got {:?}", other),
+        }
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_drop_after_timeout() {
+        // Nothing listens on these ports, so requests are never sent
+        let listeners: Vec<_> = (0..5)
+            .map(|_| std::net::TcpListener::bind("127.0.0.1:0").unwrap())
+            .collect();
+        let ports: Vec<_> = listeners
+            .iter()
+            .map(|l| l.local_addr().unwrap().port())
+            .collect();
+        drop(listeners);
+        let info = json!({
+            "shell_port": ports[0],
+            "iopub_port": ports[1],
+            "stdin_port": ports[2],
+            "control_port": ports[3],
+            "hb_port": ports[4],
+            "ip": "127.0.0.1",
+            "key": "secret",
+            "transport": "tcp",
+            "signature_scheme": "hmac-sha256",
+            "kernel_name": "fake",
+        });
+        let client = Client::from_reader(info.to_string().as_bytes()).unwrap();
+        let timeout = Duration::from_millis(100);
+        // Requests are queued, or fail at once if the connection has already been refused
+        match client.send_control_command_timeout(Command::Interrupt, timeout) {
+            Err(Error::Timeout(_)) | Err(Error::KernelDead) => {}
+            other => unreachable!("expected a timeout, got {:?}", other),
+        }
+        match client.send_shell_command_timeout(is_complete(), timeout) {
+            Err(Error::Timeout(_)) | Err(Error::KernelDead) => {}
+            other => unreachable!("expected a timeout, got {:?}", other),
+        }
+
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            drop(client);
+            tx.send(()).unwrap();
+        });
+        rx.recv_timeout(Duration::from_secs(5))
+            .expect("dropping the client should not wait for unsent requests");
+    }
+
+    #[test]
+    fn test_interrupt_errors() {
+        let kernel = FakeKernel::new();
+        let mut client = kernel.client();
+        match client.interrupt() {
+            Err(Error::InvalidOperation(_)) => {}
+            other => unreachable!("expected an unknown pid, got {:?}", other),
+        }
+
+        // The kernel never replies
+        let timeout = Duration::from_millis(100);
+        client.set_interrupt_mode(InterruptMode::Message);
+        client.set_timeout(Some(timeout));
+        match client.interrupt() {
+            Err(Error::Timeout(t)) => assert_eq!(t, timeout),
+            other => unreachable!("expected a timeout, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_replace_input_handler() {
+        let kernel = FakeKernel::new();
+        let client = kernel.client();
+        client
+            .set_input_handler(|_: &str, _: bool| "first".to_string())
+            .unwrap();
+        client
+            .set_input_handler(|_: &str, _: bool| "second".to_string())
+            .unwrap();
+
+        // The kernel routes input requests to the sender of a shell request
+        let _ = client.send_shell_command_timeout(is_complete(), Duration::from_millis(100));
+        let request = kernel.recv(&kernel.shell);
+        let ask = || {
+            kernel.reply(
+                &kernel.stdin,
+                &request,
+                "input_request",
+                json!({"prompt": "Name: ", "password": false}),
+            );
+            let reply = kernel.recv(&kernel.stdin);
+            let content: serde_json::Value = serde_json::from_slice(&reply.content).unwrap();
+            content["value"].as_str().unwrap().to_string()
+        };
+        assert_eq!(ask(), "second");
+        client
+            .set_input_handler(|_: &str, _: bool| "third".to_string())
+            .unwrap();
+        assert_eq!(ask(), "third");
+
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            drop(client);
+            tx.send(()).unwrap();
+        });
+        rx.recv_timeout(Duration::from_secs(5))
+            .expect("dropping the client should stop the stdin thread");
+    }
+
+    #[test]
+    fn test_finished_subscribers_removed() {
+        let kernel = FakeKernel::new();
+        let mut client = kernel.client();
+        client.set_timeout(Some(Duration::from_secs(5)));
+        kernel.wait_for_subscriber();
+
+        let handle = thread::spawn(move || {
+            let request = kernel.recv(&kernel.shell);
+            kernel.reply(
+                &kernel.shell,
+                &request,
+                "execute_reply",
+                json!({"status": "ok", "execution_count": 1, "user_expressions": {}}),
+            );
+            kernel.reply(
+                &kernel.iopub,
+                &request,
+                "status",
+                json!({"execution_state": "idle"}),
+            );
+            // Neither of these is wanted by anyone left
+            kernel.recv(&kernel.shell);
+            kernel.publish("stream", json!({"name": "stdout", "text": "late\n"}));
+        });
+
+        client.execute("1").unwrap();
+        assert_eq!(client.iopub.subscriber_count(), 0);
+
+        let started = client.start_execute("2").unwrap();
+        let errors = client
+            .iopub_subscribe_with(IoPubSubscription::new().msg_type("error"))
+            .unwrap();
+        assert_eq!(client.iopub.subscriber_count(), 2);
+        drop(started);
+        drop(errors);
+        assert_eq!(client.iopub.subscriber_count(), 0);
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_execute_collects_outputs() {
+        let kernel = FakeKernel::new();
+        let mut client = kernel.client();
+        client.set_timeout(Some(Duration::from_secs(5)));
+        kernel.wait_for_subscriber();
+
+        let handle = thread::spawn(move || {
+            let request = kernel.recv(&kernel.shell);
+            let publish =
+                |msg_type, content| kernel.reply(&kernel.iopub, &request, msg_type, content);
+            publish("status", json!({"execution_state": "busy"}));
+            publish(
+                "execute_input",
+                json!({"code": "print(1); 2", "execution_count": 3}),
+            );
+            kernel.publish("stream", json!({"name": "stdout", "text": "not ours\n"}));
+            publish("stream", json!({"name": "stdout", "text": "1\n"}));
+            publish(
+                "display_data",
+                json!({"data": {"text/plain": "<figure>"}, "metadata": {}, "transient": {}}),
+            );
+            publish(
+                "execute_result",
+                json!({"execution_count": 3, "data": {"text/plain": "2"}, "metadata": {}}),
+            );
+            kernel.reply(
+                &kernel.shell,
+                &request,
+                "execute_reply",
+                json!({"status": "ok", "execution_count": 3, "user_expressions": {}}),
+            );
+            publish("status", json!({"execution_state": "idle"}));
+        });
+
+        let outcome = client.execute("print(1); 2").unwrap();
+        handle.join().unwrap();
+        assert!(outcome.is_ok());
+        assert_eq!(outcome.execution_count, 3);
+        assert_eq!(outcome.reply.status, Status::Ok);
+        match &outcome.outputs[..] {
+            [Output::Stream(stream), Output::DisplayData(_), Output::ExecuteResult(result)] => {
+                assert_eq!(stream.text, "1\n");
+                assert_eq!(result.execution_count, 3);
+            }
+            other => unreachable!("unexpected outputs {:?}", other),
+        }
+    }
 }
@@ -0,0 +1,363 @@
+/*! Client-side bookkeeping for comms.
+
+[Comms][comms] are bidirectional channels between the client and objects in the kernel, used by
+e.g. widget libraries. A [`CommManager`](struct.CommManager.html) keeps track of open comms and
+routes messages arriving on IOPub to a [`CommHandler`](trait.CommHandler.html) per `comm_id`.
+Comms opened by the kernel are passed to the [`CommTarget`](trait.CommTarget.html) registered
+for their `target_name`.
+
+The manager does not talk to the kernel itself. Any commands it returns should be sent with
+[`Client::send_comm_command`][send-comm-command].
+
+```no_run
+# use jupyter_client::{Result, Client};
+use jupyter_client::comm::CommManager;
+use jupyter_client::responses::CommMsgContent;
+use serde_json::json;
+# fn main() -> Result<()> {
+let client = Client::existing()?;
+let receiver = client.iopub_subscribe()?;
+let mut manager = CommManager::new();
+
+// Open a comm to a target registered in the kernel
+let (_comm_id, open) = manager.open(
+    "my_target",
+    json!({}),
+    |msg: &CommMsgContent, _buffers: &[Vec<u8>]| {
+        println!("received {:?}", msg.data);
+    },
+);
+client.send_comm_command(open)?;
+
+for msg in receiver {
+    if let Some(reply) = manager.handle(&msg?) {
+        client.send_comm_command(reply)?;
+    }
+}
+# Ok(())
+# }
+```
+
+[comms]: https://jupyter-client.readthedocs.io/en/stable/messaging.html#custom-messages
+[send-comm-command]: ../struct.Client.html#method.send_comm_command
+*/
+use crate::commands::Command;
+use crate::responses::{
+    CommCloseContent, CommMsgContent, CommOpenContent, IoPubResponse, Response,
+};
+use log::{debug, warn};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fmt;
+
+/** Handler for messages on a single comm.
+
+Any `FnMut(&CommMsgContent, &[Vec<u8>])` closure can be used as a handler that ignores close
+messages. The second argument holds the binary buffers sent with the message.
+*/
+pub trait CommHandler: Send {
+    /// Called for every `comm_msg` the kernel sends on this comm.
+    fn on_msg(&mut self, msg: &CommMsgContent, buffers: &[Vec<u8>]);
+
+    /// Called when the kernel closes this comm.
+    fn on_close(&mut self, _msg: &CommCloseContent, _buffers: &[Vec<u8>]) {}
+}
+
+impl<F> CommHandler for F
+where
+    F: FnMut(&CommMsgContent, &[Vec<u8>]) + Send,
+{
+    fn on_msg(&mut self, msg: &CommMsgContent, buffers: &[Vec<u8>]) {
+        self(msg, buffers)
+    }
+}
+
+/** Opener for comms created by the kernel with a given `target_name`.
+
+Returning `None` rejects the comm, and the manager asks the kernel to close it.
+*/
+pub trait CommTarget: Send {
+    /// Called when the kernel opens a comm for this target.
+    fn open(&mut self, msg: &CommOpenContent, buffers: &[Vec<u8>]) -> Option<Box<dyn CommHandler>>;
+}
+
+impl<F> CommTarget for F
+where
+    F: FnMut(&CommOpenContent, &[Vec<u8>]) -> Option<Box<dyn CommHandler>> + Send,
+{
+    fn open(&mut self, msg: &CommOpenContent, buffers: &[Vec<u8>]) -> Option<Box<dyn CommHandler>> {
+        self(msg, buffers)
+    }
+}
+
+struct Comm {
+    target_name: String,
+    handler: Box<dyn CommHandler>,
+}
+
+/// Routes comm messages to per-comm handlers.
+#[derive(Default)]
+pub struct CommManager {
+    targets: HashMap<String, Box<dyn CommTarget>>,
+    comms: HashMap<String, Comm>,
+}
+
+impl fmt::Debug for CommManager {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CommManager")
+            .field("targets", &self.targets.keys().collect::<Vec<_>>())
+            .field("comms", &self.comms.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl CommManager {
+    /// Create an empty manager.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register an opener for comms the kernel opens with `target_name`.
+    pub fn register_target<S, T>(&mut self, target_name: S, target: T)
+    where
+        S: Into<String>,
+        T: CommTarget + 'static,
+    {
+        self.targets.insert(target_name.into(), Box::new(target));
+    }
+
+    /// Remove the opener for `target_name`. Comms that are already open are not affected.
+    pub fn unregister_target(&mut self, target_name: &str) {
+        self.targets.remove(target_name);
+    }
+
+    /** Open a new comm from the client side.
+
+    The handler is registered straight away, and the returned `comm_open` command must be sent
+    to the kernel. Returns the new `comm_id` along with the command.
+    */
+    pub fn open<S, H>(&mut self, target_name: S, data: Value, handler: H) -> (String, Command)
+    where
+        S: Into<String>,
+        H: CommHandler + 'static,
+    {
+        let comm_id = uuid::Uuid::new_v4().to_string();
+        let target_name = target_name.into();
+        self.comms.insert(
+            comm_id.clone(),
+            Comm {
+                target_name: target_name.clone(),
+                handler: Box::new(handler),
+            },
+        );
+        let command = Command::CommOpen {
+            comm_id: comm_id.clone(),
+            target_name,
+            data,
+            buffers: Vec::new(),
+        };
+        (comm_id, command)
+    }
+
+    /** Build a `comm_msg` command for an open comm, with optional binary buffers.
+
+    Returns `None` if the comm is not open.
+    */
+    pub fn send(&self, comm_id: &str, data: Value, buffers: Vec<Vec<u8>>) -> Option<Command> {
+        if !self.comms.contains_key(comm_id) {
+            return None;
+        }
+        Some(Command::CommMsg {
+            comm_id: comm_id.to_string(),
+            data,
+            buffers,
+        })
+    }
+
+    /** Close a comm from the client side.
+
+    The handler is dropped, and the returned `comm_close` command must be sent to the kernel.
+    Returns `None` if the comm is not open.
+    */
+    pub fn close(&mut self, comm_id: &str, data: Value) -> Option<Command> {
+        self.comms.remove(comm_id).map(|_| Command::CommClose {
+            comm_id: comm_id.to_string(),
+            data,
+            buffers: Vec::new(),
+        })
+    }
+
+    /// Whether the comm is currently open.
+    pub fn is_open(&self, comm_id: &str) -> bool {
+        self.comms.contains_key(comm_id)
+    }
+
+    /// Target name of an open comm.
+    pub fn target_name(&self, comm_id: &str) -> Option<&str> {
+        self.comms.get(comm_id).map(|c| c.target_name.as_str())
+    }
+
+    /// Ids of all open comms.
+    pub fn comm_ids(&self) -> impl Iterator<Item = &str> {
+        self.comms.keys().map(|k| k.as_str())
+    }
+
+    /** Route a message from the kernel.
+
+    Non-comm messages are ignored. If a reply must be sent to the kernel, for example to close a
+    comm opened for an unknown target, it is returned.
+    */
+    pub fn handle(&mut self, response: &Response) -> Option<Command> {
+        match response {
+            Response::IoPub(IoPubResponse::CommOpen {
+                content, buffers, ..
+            }) => self.handle_open(content, buffers),
+            Response::IoPub(IoPubResponse::CommMsg {
+                content, buffers, ..
+            }) => {
+                match self.comms.get_mut(&content.comm_id) {
+                    Some(comm) => comm.handler.on_msg(content, buffers),
+                    None => debug!("message for unknown comm {}", content.comm_id),
+                }
+                None
+            }
+            Response::IoPub(IoPubResponse::CommClose {
+                content, buffers, ..
+            }) => {
+                match self.comms.remove(&content.comm_id) {
+                    Some(mut comm) => comm.handler.on_close(content, buffers),
+                    None => debug!("close for unknown comm {}", content.comm_id),
+                }
+                None
+            }
+            _ => None,
+        }
+    }
+
+    fn handle_open(&mut self, content: &CommOpenContent, buffers: &[Vec<u8>]) -> Option<Command> {
+        let handler = match self.targets.get_mut(&content.target_name) {
+            Some(target) => target.open(content, buffers),
+            None => {
+                warn!("no comm target registered for {:?}", content.target_name);
+                None
+            }
+        };
+
+        match handler {
+            Some(handler) => {
+                self.comms.insert(
+                    content.comm_id.clone(),
+                    Comm {
+                        target_name: content.target_name.clone(),
+                        handler,
+                    },
+                );
+                None
+            }
+            None => Some(Command::CommClose {
+                comm_id: content.comm_id.clone(),
+                data: json!({}),
+                buffers: Vec::new(),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::header::{Header, Session};
+    use crate::metadata::Metadata;
+    use std::sync::{Arc, Mutex};
+
+    fn iopub(response: IoPubResponse) -> Response {
+        Response::IoPub(response)
+    }
+
+    fn comm_msg(comm_id: &str, data: Value) -> Response {
+        iopub(IoPubResponse::CommMsg {
+            header: Header::new("comm_msg", &Session::default()),
+            parent_header: Some(Header::new("comm_msg", &Session::default())),
+            metadata: Metadata::default(),
+            content: CommMsgContent {
+                comm_id: comm_id.to_string(),
+                data,
+            },
+            buffers: vec![b"buffer".to_vec()],
+            identities: Vec::new(),
+        })
+    }
+
+    fn comm_open(comm_id: &str, target_name: &str) -> Response {
+        iopub(IoPubResponse::CommOpen {
+            header: Header::new("comm_open", &Session::default()),
+            parent_header: Some(Header::new("execute_request", &Session::default())),
+            metadata: Metadata::default(),
+            content: CommOpenContent {
+                comm_id: comm_id.to_string(),
+                target_name: target_name.to_string(),
+                target_module: None,
+                data: json!({}),
+            },
+            buffers: Vec::new(),
+            identities: Vec::new(),
+        })
+    }
+
+    #[test]
+    fn test_client_opened_comm_receives_messages() {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let mut manager = CommManager::new();
+        let sink = received.clone();
+        let (comm_id, command) = manager.open(
+            "target",
+            json!({}),
+            move |m: &CommMsgContent, buffers: &[Vec<u8>]| {
+                sink.lock()
+                    .unwrap()
+                    .push((m.data.clone(), buffers.to_vec()));
+            },
+        );
+
+        match command {
+            Command::CommOpen {
+                comm_id: ref id,
+                ref target_name,
+                ..
+            } => {
+                assert_eq!(id, &comm_id);
+                assert_eq!(target_name, "target");
+            }
+            _ => unreachable!("should be a CommOpen command"),
+        }
+
+        assert!(manager.handle(&comm_msg(&comm_id, json!(1))).is_none());
+        assert!(manager.handle(&comm_msg("other", json!(2))).is_none());
+        assert_eq!(
+            *received.lock().unwrap(),
+            vec![(json!(1), vec![b"buffer".to_vec()])]
+        );
+
+        assert!(manager.close(&comm_id, json!({})).is_some());
+        assert!(!manager.is_open(&comm_id));
+        assert!(manager.send(&comm_id, json!({}), Vec::new()).is_none());
+    }
+
+    #[test]
+    fn test_kernel_opened_comm_uses_target() {
+        let mut manager = CommManager::new();
+        manager.register_target("known", |_: &CommOpenContent, _: &[Vec<u8>]| {
+            Some(Box::new(|_: &CommMsgContent, _: &[Vec<u8>]| {}) as Box<dyn CommHandler>)
+        });
+
+        assert!(manager.handle(&comm_open("a", "known")).is_none());
+        assert!(manager.is_open("a"));
+        assert_eq!(manager.target_name("a"), Some("known"));
+
+        match manager.handle(&comm_open("b", "unknown")) {
+            Some(Command::CommClose { comm_id, .. }) => assert_eq!(comm_id, "b"),
+            _ => unreachable!("unknown targets should be closed"),
+        }
+        assert!(!manager.is_open("b"));
+    }
+}
@@ -8,13 +8,13 @@ These should be constructed, and then sent to the kernel via
 [send-control-command]: ../struct.Client.html#method.send_control_command
 */
 use crate::errors::Result;
-use crate::header::Header;
+use crate::header::{Header, Session};
+use crate::signatures::Signer;
 use crate::wire::WireMessage;
-use hmac::Mac;
 use log::trace;
 use serde::{Serialize as SerdeSerialize, Serializer};
 use serde_derive::Serialize;
-use serde_json::json;
+use serde_json::{json, Value};
 use std::collections::HashMap;
 use std::fmt::Debug;
 
@@ -129,61 +129,119 @@ pub enum Command {
         /// The target name
         target_name: Option<String>,
     },
+    /** Open a comm to the kernel.
+
+    Comm messages do not get a reply. Send them with
+    [`Client::send_comm_command`](../struct.Client.html#method.send_comm_command).
+    */
+    CommOpen {
+        /// Unique id of the new comm.
+        comm_id: String,
+        /// Name of the kernel-side target that should handle the comm.
+        target_name: String,
+        /// Data to send with the open message.
+        data: Value,
+        /// Binary buffers to send after the message content.
+        buffers: Vec<Vec<u8>>,
+    },
+    /// Send a message on an open comm.
+    CommMsg {
+        /// Id of the comm.
+        comm_id: String,
+        /// Data to send.
+        data: Value,
+        /// Binary buffers to send after the message content.
+        buffers: Vec<Vec<u8>>,
+    },
+    /// Close an open comm.
+    CommClose {
+        /// Id of the comm.
+        comm_id: String,
+        /// Data to send with the close message.
+        data: Value,
+        /// Binary buffers to send after the message content.
+        buffers: Vec<Vec<u8>>,
+    },
+    /** Interrupt the kernel.
+
+    This should be sent on the control channel, and is only honoured by kernels with an
+    `interrupt_mode` of `message` in their kernelspec. See
+    [`Client::interrupt`](../struct.Client.html#method.interrupt) to pick the right mechanism
+    automatically.
+    */
+    Interrupt,
+    /** Reply to an `input_request` from the kernel.
+
+    This is sent on the stdin channel, usually by the client's registered
+    [`InputHandler`](../stdin/trait.InputHandler.html) rather than by hand.
+    */
+    InputReply {
+        /// The text entered by the user.
+        value: String,
+    },
 }
 
 impl Command {
-    pub(crate) fn into_wire<M: Mac + Debug>(self, auth: M) -> Result<WireMessage<M>> {
+    pub(crate) fn into_wire<M: Signer>(self, auth: M, session: &Session) -> Result<WireMessage<M>> {
         let msg = match self {
             Command::KernelInfo => {
-                let header = Header::new("kernel_info_request");
+                let header = Header::new("kernel_info_request", session);
                 let header_bytes = header.to_bytes()?;
                 Ok(WireMessage {
+                    identities: Vec::new(),
                     header: header_bytes.to_vec(),
                     parent_header: b"{}".to_vec(),
                     metadata: b"{}".to_vec(),
                     content: b"{}".to_vec(),
+                    buffers: Vec::new(),
                     auth,
                 })
             }
             r @ Command::Execute { .. } => {
-                let header = Header::new("execute_request");
+                let header = Header::new("execute_request", session);
                 let header_bytes = header.to_bytes()?;
                 let content_str = serde_json::to_string(&r)?;
                 let content = content_str.into_bytes();
 
                 Ok(WireMessage {
+                    identities: Vec::new(),
                     header: header_bytes.to_vec(),
                     parent_header: b"{}".to_vec(),
                     metadata: b"{}".to_vec(),
                     content,
+                    buffers: Vec::new(),
                     auth,
                 })
             }
             r @ Command::Inspect { .. } => {
-                let header = Header::new("inspect_request");
+                let header = Header::new("inspect_request", session);
                 let header_bytes = header.to_bytes()?;
                 let content_str = serde_json::to_string(&r)?;
                 let content = content_str.into_bytes();
 
                 Ok(WireMessage {
+                    identities: Vec::new(),
                     header: header_bytes.to_vec(),
                     parent_header: b"{}".to_vec(),
                     metadata: b"{}".to_vec(),
                     content,
+                    buffers: Vec::new(),
                     auth,
                 })
             }
             r @ Command::Complete { .. } => {
-                let header = Header::new("complete_request");
+                let header = Header::new("complete_request", session);
                 let header_bytes = header.to_bytes()?;
                 let content_str = serde_json::to_string(&r)?;
                 let content = content_str.into_bytes();
 
                 Ok(WireMessage {
+                    identities: Vec::new(),
                     header: header_bytes.to_vec(),
                     parent_header: b"{}".to_vec(),
                     metadata: b"{}".to_vec(),
                     content,
+                    buffers: Vec::new(),
                     auth,
                 })
             }
@@ -193,7 +251,7 @@ impl Command {
                 hist_access_type,
                 unique,
             } => {
-                let header = Header::new("history_request");
+                let header = Header::new("history_request", session);
                 let header_bytes = header.to_bytes()?;
 
                 let content = match hist_access_type {
@@ -216,7 +274,7 @@ impl Command {
                             "output": output,
                             "raw": raw,
                             "unique": unique,
-                            "hist_access_type": "tail",
+                            "hist_access_type": "range",
                             "session": session,
                             "start": start,
                             "stop": stop,
@@ -227,7 +285,7 @@ impl Command {
                             "output": output,
                             "raw": raw,
                             "unique": unique,
-                            "hist_access_type": "tail",
+                            "hist_access_type": "search",
                             "session": null,
                             "start": null,
                             "stop": null,
@@ -240,15 +298,17 @@ impl Command {
                 let content = content_str.into_bytes();
 
                 Ok(WireMessage {
+                    identities: Vec::new(),
                     header: header_bytes.to_vec(),
                     parent_header: b"{}".to_vec(),
                     metadata: b"{}".to_vec(),
                     content,
+                    buffers: Vec::new(),
                     auth,
                 })
             }
             Command::IsComplete { code } => {
-                let header = Header::new("is_complete_request");
+                let header = Header::new("is_complete_request", session);
                 let header_bytes = header.to_bytes()?;
 
                 let content_json = json!({
@@ -258,15 +318,17 @@ impl Command {
                 let content = content_str.into_bytes();
 
                 Ok(WireMessage {
+                    identities: Vec::new(),
                     header: header_bytes.to_vec(),
                     parent_header: b"{}".to_vec(),
                     metadata: b"{}".to_vec(),
-                    content: content,
+                    content,
+                    buffers: Vec::new(),
                     auth,
                 })
             }
             Command::Shutdown { restart } => {
-                let header = Header::new("shutdown_request");
+                let header = Header::new("shutdown_request", session);
                 let header_bytes = header.to_bytes()?;
                 let content_json = json!({
                     "restart": restart,
@@ -275,15 +337,17 @@ impl Command {
                 let content = content_str.into_bytes();
 
                 Ok(WireMessage {
+                    identities: Vec::new(),
                     header: header_bytes.to_vec(),
                     parent_header: b"{}".to_vec(),
                     metadata: b"{}".to_vec(),
                     content,
+                    buffers: Vec::new(),
                     auth,
                 })
             }
             Command::CommInfo { target_name } => {
-                let header = Header::new("comm_info_request");
+                let header = Header::new("comm_info_request", session);
                 let header_bytes = header.to_bytes()?;
                 let content_json = match target_name {
                     Some(target_name) => json!({
@@ -295,10 +359,115 @@ impl Command {
                 let content = content_str.into_bytes();
 
                 Ok(WireMessage {
+                    identities: Vec::new(),
+                    header: header_bytes.to_vec(),
+                    parent_header: b"{}".to_vec(),
+                    metadata: b"{}".to_vec(),
+                    content,
+                    buffers: Vec::new(),
+                    auth,
+                })
+            }
+            Command::CommOpen {
+                comm_id,
+                target_name,
+                data,
+                buffers,
+            } => {
+                let header = Header::new("comm_open", session);
+                let header_bytes = header.to_bytes()?;
+                let content_json = json!({
+                    "comm_id": comm_id,
+                    "target_name": target_name,
+                    "data": data,
+                });
+                let content_str = serde_json::to_string(&content_json)?;
+                let content = content_str.into_bytes();
+
+                Ok(WireMessage {
+                    identities: Vec::new(),
+                    header: header_bytes.to_vec(),
+                    parent_header: b"{}".to_vec(),
+                    metadata: b"{}".to_vec(),
+                    content,
+                    buffers,
+                    auth,
+                })
+            }
+            Command::CommMsg {
+                comm_id,
+                data,
+                buffers,
+            } => {
+                let header = Header::new("comm_msg", session);
+                let header_bytes = header.to_bytes()?;
+                let content_json = json!({
+                    "comm_id": comm_id,
+                    "data": data,
+                });
+                let content_str = serde_json::to_string(&content_json)?;
+                let content = content_str.into_bytes();
+
+                Ok(WireMessage {
+                    identities: Vec::new(),
+                    header: header_bytes.to_vec(),
+                    parent_header: b"{}".to_vec(),
+                    metadata: b"{}".to_vec(),
+                    content,
+                    buffers,
+                    auth,
+                })
+            }
+            Command::CommClose {
+                comm_id,
+                data,
+                buffers,
+            } => {
+                let header = Header::new("comm_close", session);
+                let header_bytes = header.to_bytes()?;
+                let content_json = json!({
+                    "comm_id": comm_id,
+                    "data": data,
+                });
+                let content_str = serde_json::to_string(&content_json)?;
+                let content = content_str.into_bytes();
+
+                Ok(WireMessage {
+                    identities: Vec::new(),
                     header: header_bytes.to_vec(),
                     parent_header: b"{}".to_vec(),
                     metadata: b"{}".to_vec(),
                     content,
+                    buffers,
+                    auth,
+                })
+            }
+            Command::Interrupt => {
+                let header = Header::new("interrupt_request", session);
+                let header_bytes = header.to_bytes()?;
+                Ok(WireMessage {
+                    identities: Vec::new(),
+                    header: header_bytes.to_vec(),
+                    parent_header: b"{}".to_vec(),
+                    metadata: b"{}".to_vec(),
+                    content: b"{}".to_vec(),
+                    buffers: Vec::new(),
+                    auth,
+                })
+            }
+            r @ Command::InputReply { .. } => {
+                let header = Header::new("input_reply", session);
+                let header_bytes = header.to_bytes()?;
+                let content_str = serde_json::to_string(&r)?;
+                let content = content_str.into_bytes();
+
+                Ok(WireMessage {
+                    identities: Vec::new(),
+                    header: header_bytes.to_vec(),
+                    parent_header: b"{}".to_vec(),
+                    metadata: b"{}".to_vec(),
+                    content,
+                    buffers: Vec::new(),
                     auth,
                 })
             }
@@ -359,3 +528,44 @@ impl SerdeSerialize for DetailLevel {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signatures::Auth;
+
+    fn history_content(hist_access_type: HistoryAccessType) -> Value {
+        let command = Command::History {
+            output: false,
+            raw: true,
+            hist_access_type,
+            unique: false,
+        };
+        let wire = command
+            .into_wire(Auth::new("hmac-sha256", b"").unwrap(), &Session::default())
+            .unwrap();
+        serde_json::from_slice(&wire.content).unwrap()
+    }
+
+    #[test]
+    fn test_history_access_types() {
+        let content = history_content(HistoryAccessType::Tail { n: 10 });
+        assert_eq!(content["hist_access_type"], "tail");
+        assert_eq!(content["n"], 10);
+
+        let content = history_content(HistoryAccessType::Range {
+            session: -1,
+            start: 1,
+            stop: 5,
+        });
+        assert_eq!(content["hist_access_type"], "range");
+        assert_eq!(content["session"], -1);
+        assert_eq!(content["stop"], 5);
+
+        let content = history_content(HistoryAccessType::Search {
+            pattern: "import *".to_string(),
+        });
+        assert_eq!(content["hist_access_type"], "search");
+        assert_eq!(content["pattern"], "import *");
+    }
+}
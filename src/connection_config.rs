@@ -1,7 +1,7 @@
 use crate::errors::Result;
-use serde_derive::Deserialize;
+use serde_derive::{Deserialize, Serialize};
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ConnectionConfig {
     pub shell_port: u32,
     pub iopub_port: u32,
@@ -11,10 +11,16 @@ pub struct ConnectionConfig {
     pub ip: String,
     pub key: String,
     pub transport: String,
+    #[serde(default = "default_signature_scheme")]
     pub signature_scheme: String,
+    #[allow(dead_code)]
     pub kernel_name: String,
 }
 
+fn default_signature_scheme() -> String {
+    "hmac-sha256".to_string()
+}
+
 impl ConnectionConfig {
     pub(crate) fn from_reader<R>(reader: R) -> Result<Self>
     where
@@ -0,0 +1,191 @@
+/*! Tracking of updatable displays.
+
+Kernels can attach a `display_id` to `display_data` messages, and later replace the displayed
+content with `update_display_data` messages carrying the same id. This is how progress bars and
+live plots work. A [`DisplayRegistry`](struct.DisplayRegistry.html) keeps the current MIME
+bundle for each display id.
+
+```no_run
+# use jupyter_client::{Result, Client};
+use jupyter_client::display::DisplayRegistry;
+# fn main() -> Result<()> {
+let client = Client::existing()?;
+let mut registry = DisplayRegistry::new();
+
+for msg in client.iopub_subscribe()? {
+    if let Some(display_id) = registry.handle(&msg?) {
+        println!("{}: {:?}", display_id, registry.get(&display_id));
+    }
+}
+# Ok(())
+# }
+```
+*/
+use crate::responses::{DisplayDataContent, IoPubResponse, Response};
+use log::debug;
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// Current contents of a display.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Display {
+    /// MIME bundle, mapping MIME types to the representation in that format.
+    pub data: HashMap<String, Value>,
+    /// Metadata describing the data.
+    pub metadata: HashMap<String, Value>,
+}
+
+impl<'a> From<&'a DisplayDataContent> for Display {
+    fn from(content: &'a DisplayDataContent) -> Self {
+        Display {
+            data: content.data.clone(),
+            metadata: content.metadata.clone(),
+        }
+    }
+}
+
+/// Maps display ids to the current contents of the display.
+#[derive(Debug, Default)]
+pub struct DisplayRegistry {
+    displays: HashMap<String, Display>,
+}
+
+impl DisplayRegistry {
+    /// Create an empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /** Apply a message from the kernel.
+
+    `display_data` messages with a display id create or replace the display, and
+    `update_display_data` messages replace displays that are already known. Updates for unknown
+    ids are ignored, as there is nothing on screen to update. Returns the id of the display that
+    changed, if any.
+    */
+    pub fn handle(&mut self, response: &Response) -> Option<String> {
+        let (content, is_update) = match response {
+            Response::IoPub(IoPubResponse::DisplayData { content, .. }) => (content, false),
+            Response::IoPub(IoPubResponse::UpdateDisplayData { content, .. }) => (content, true),
+            _ => return None,
+        };
+        let display_id = content.display_id()?;
+
+        if is_update {
+            match self.displays.get_mut(display_id) {
+                Some(display) => *display = Display::from(content),
+                None => {
+                    debug!("update for unknown display {}", display_id);
+                    return None;
+                }
+            }
+        } else {
+            self.displays
+                .insert(display_id.to_string(), Display::from(content));
+        }
+
+        Some(display_id.to_string())
+    }
+
+    /// Current contents of a display.
+    pub fn get(&self, display_id: &str) -> Option<&Display> {
+        self.displays.get(display_id)
+    }
+
+    /// Stop tracking a display.
+    pub fn remove(&mut self, display_id: &str) -> Option<Display> {
+        self.displays.remove(display_id)
+    }
+
+    /// Iterate over all tracked displays.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Display)> {
+        self.displays.iter().map(|(k, v)| (k.as_str(), v))
+    }
+
+    /// Number of tracked displays.
+    pub fn len(&self) -> usize {
+        self.displays.len()
+    }
+
+    /// Whether no displays are tracked.
+    pub fn is_empty(&self) -> bool {
+        self.displays.is_empty()
+    }
+
+    /// Stop tracking all displays.
+    pub fn clear(&mut self) {
+        self.displays.clear()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::header::{Header, Session};
+    use crate::metadata::Metadata;
+    use crate::responses::Transient;
+    use serde_json::json;
+
+    fn content(display_id: Option<&str>, text: &str) -> DisplayDataContent {
+        let mut data = HashMap::new();
+        data.insert("text/plain".to_string(), json!(text));
+        DisplayDataContent {
+            data,
+            metadata: HashMap::new(),
+            transient: Some(Transient {
+                display_id: display_id.map(String::from),
+            }),
+        }
+    }
+
+    fn display(display_id: Option<&str>, text: &str) -> Response {
+        Response::IoPub(IoPubResponse::DisplayData {
+            header: Header::new("display_data", &Session::default()),
+            parent_header: Some(Header::new("execute_request", &Session::default())),
+            metadata: Metadata::default(),
+            content: content(display_id, text),
+            buffers: Vec::new(),
+            identities: Vec::new(),
+        })
+    }
+
+    fn update(display_id: &str, text: &str) -> Response {
+        Response::IoPub(IoPubResponse::UpdateDisplayData {
+            header: Header::new("update_display_data", &Session::default()),
+            parent_header: Some(Header::new("execute_request", &Session::default())),
+            metadata: Metadata::default(),
+            content: content(Some(display_id), text),
+            buffers: Vec::new(),
+            identities: Vec::new(),
+        })
+    }
+
+    #[test]
+    fn test_updates_applied_in_order() {
+        let mut registry = DisplayRegistry::new();
+        assert_eq!(
+            registry.handle(&display(Some("bar"), "0%")),
+            Some("bar".to_string())
+        );
+        assert_eq!(
+            registry.handle(&update("bar", "50%")),
+            Some("bar".to_string())
+        );
+        assert_eq!(
+            registry.handle(&update("bar", "100%")),
+            Some("bar".to_string())
+        );
+
+        let current = registry.get("bar").unwrap();
+        assert_eq!(current.data["text/plain"], "100%");
+        assert_eq!(registry.len(), 1);
+    }
+
+    #[test]
+    fn test_ignores_untracked_displays() {
+        let mut registry = DisplayRegistry::new();
+        assert_eq!(registry.handle(&display(None, "plain")), None);
+        assert_eq!(registry.handle(&update("unknown", "50%")), None);
+        assert!(registry.is_empty());
+    }
+}
@@ -1,7 +1,118 @@
-use failure::Error;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
 
-/** Wrapped result type for this crate.
+/** Errors returned by this crate.
 
-This is just a `failure::Error` error type, with generic `Ok` type.
+New variants may be added in future releases, so matches should include a wildcard arm. Errors
+are cheap to clone, so they can be delivered to every IOPub subscriber.
 */
+#[derive(Debug, Clone, Error)]
+#[non_exhaustive]
+pub enum Error {
+    /// Error from the ZMQ transport.
+    #[error("transport error: {0}")]
+    Zmq(#[from] zmq::Error),
+
+    /// Error reading a connection file or signalling the kernel process.
+    #[error("io error: {0}")]
+    Io(Arc<std::io::Error>),
+
+    /// The signature of a received message does not match its contents.
+    #[error("signatures do not match")]
+    SignatureMismatch,
+
+    /// A message was received with a signature that has already been seen.
+    ///
+    /// This usually means the message was replayed.
+    #[error("duplicate signature {signature}")]
+    DuplicateSignature {
+        /// The repeated signature.
+        signature: String,
+    },
+
+    /// A received message does not have the frames required by the wire protocol.
+    #[error("malformed message frames: {0}")]
+    MalformedFrames(String),
+
+    /// A message, or part of one, is not valid JSON of the expected shape.
+    #[error("cannot decode {}: {source}", msg_type.as_deref().unwrap_or("message"))]
+    Json {
+        /// Type of the message being decoded, if its header could be read.
+        msg_type: Option<String>,
+        /// The underlying decoding error.
+        #[source]
+        source: Arc<serde_json::Error>,
+    },
+
+    /// No reply arrived within the allowed time.
+    #[error("timed out after {0:?}")]
+    Timeout(Duration),
+
+    /// The kernel stopped responding, or its process exited.
+    #[error("kernel is dead")]
+    KernelDead,
+
+    /// The kernel sent a message that breaks the messaging protocol.
+    #[error("protocol violation: {0}")]
+    Protocol(String),
+
+    /// The connection information or configuration is invalid.
+    #[error("invalid configuration: {0}")]
+    Config(String),
+
+    /// No kernelspec is installed with the given name.
+    #[error("no kernel named {0}")]
+    NoSuchKernel(String),
+
+    /// A kernelspec does not follow the kernelspec format.
+    #[error("invalid kernelspec {}: {}", path.display(), problems.join("; "))]
+    InvalidKernelSpec {
+        /// Path of the `kernel.json` file.
+        path: PathBuf,
+        /// Description of each problem found.
+        problems: Vec<String>,
+    },
+
+    /// The operation cannot be performed with the given arguments or client state.
+    #[error("invalid operation: {0}")]
+    InvalidOperation(String),
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(Arc::new(e))
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(source: serde_json::Error) -> Self {
+        Error::Json {
+            msg_type: None,
+            source: Arc::new(source),
+        }
+    }
+}
+
+/// Wrapped result type for this crate, with the crate's [`Error`](enum.Error.html) type.
 pub type Result<T> = ::std::result::Result<T, Error>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_error_context() {
+        let source = serde_json::from_str::<u32>("{").unwrap_err();
+        let err = Error::Json {
+            msg_type: Some("execute_reply".to_string()),
+            source: Arc::new(source),
+        };
+        assert!(err.to_string().starts_with("cannot decode execute_reply: "));
+        assert!(std::error::Error::source(&err).is_some());
+
+        let err: Error = serde_json::from_str::<u32>("{").unwrap_err().into();
+        assert!(err.to_string().starts_with("cannot decode message: "));
+    }
+}
@@ -0,0 +1,405 @@
+/*! Running code and collecting its output.
+
+[`Client::execute`][execute] sends an `execute_request` and gathers everything the kernel
+publishes on IOPub in response, until the kernel goes back to `idle`. This is how frontends such
+as the notebook know a cell has finished.
+
+```no_run
+# use jupyter_client::{Result, Client};
+use jupyter_client::execution::Output;
+# fn main() -> Result<()> {
+let client = Client::existing()?;
+let outcome = client.execute("print('hello')")?;
+for output in &outcome.outputs {
+    if let Output::Stream(stream) = output {
+        print!("{}", stream.text);
+    }
+}
+# Ok(())
+# }
+```
+
+To print output as soon as it arrives, [`Client::start_execute`][start-execute] returns an
+[`ExecutionHandle`](struct.ExecutionHandle.html) instead, which is an iterator over the events
+of that one request:
+
+```no_run
+# use jupyter_client::{Result, Client};
+use jupyter_client::execution::{ExecutionEvent, Output};
+# fn main() -> Result<()> {
+let client = Client::existing()?;
+let mut handle = client.start_execute("for i in range(3): print(i)")?;
+for event in &mut handle {
+    if let ExecutionEvent::Output(Output::Stream(stream)) = event? {
+        print!("{}", stream.text);
+    }
+}
+let outcome = handle.wait()?;
+# Ok(())
+# }
+```
+
+[execute]: ../struct.Client.html#method.execute
+[start-execute]: ../struct.Client.html#method.start_execute
+*/
+use crate::client::Client;
+use crate::errors::{Error, Result};
+use crate::iopub::{IoPubSubscription, StopListening};
+use crate::responses::{
+    ClearOutputContent, DisplayDataContent, ErrorContent, ExecuteReplyContent,
+    ExecuteResultContent, ExecutionState, IoPubResponse, Response, ShellResponse, Status,
+    StreamContent,
+};
+use crate::router::InFlight;
+use crate::signatures::Auth;
+use crate::wire::WireMessage;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+/// Output produced by running code.
+#[derive(Debug, Clone)]
+pub enum Output {
+    /// Text written to stdout or stderr.
+    Stream(StreamContent),
+    /// Rich output from `display_data`.
+    DisplayData(DisplayDataContent),
+    /// Replacement contents for an earlier display with the same display id.
+    UpdateDisplayData(DisplayDataContent),
+    /// The value of the last expression.
+    ExecuteResult(ExecuteResultContent),
+    /// An uncaught exception.
+    Error(ErrorContent),
+    /// Request to clear the output shown so far.
+    ClearOutput(ClearOutputContent),
+}
+
+impl Output {
+    /// The output carried by an IOPub message, if it carries any.
+    pub fn from_response(response: &Response) -> Option<Output> {
+        let output = match response {
+            Response::IoPub(IoPubResponse::Stream { content, .. }) => {
+                Output::Stream(content.clone())
+            }
+            Response::IoPub(IoPubResponse::DisplayData { content, .. }) => {
+                Output::DisplayData(content.clone())
+            }
+            Response::IoPub(IoPubResponse::UpdateDisplayData { content, .. }) => {
+                Output::UpdateDisplayData(content.clone())
+            }
+            Response::IoPub(IoPubResponse::ExecuteResult { content, .. }) => {
+                Output::ExecuteResult(content.clone())
+            }
+            Response::IoPub(IoPubResponse::Error { content, .. }) => Output::Error(content.clone()),
+            Response::IoPub(IoPubResponse::ClearOutput { content, .. }) => {
+                Output::ClearOutput(content.clone())
+            }
+            _ => return None,
+        };
+        Some(output)
+    }
+}
+
+/// Everything produced by running a piece of code.
+#[derive(Debug, Clone)]
+pub struct ExecutionOutcome {
+    /// Content of the `execute_reply`.
+    pub reply: ExecuteReplyContent,
+    /// Outputs, in the order the kernel sent them.
+    pub outputs: Vec<Output>,
+    /// Execution count the kernel assigned to this request.
+    pub execution_count: i64,
+}
+
+impl ExecutionOutcome {
+    /// Whether the code ran without raising an exception.
+    pub fn is_ok(&self) -> bool {
+        self.reply.status == Status::Ok
+    }
+}
+
+/// Something that happened while running a request.
+#[derive(Debug, Clone)]
+pub enum ExecutionEvent {
+    /// Output published by the kernel.
+    Output(Output),
+    /// The kernel changed state. The last event of a request is always `Idle`.
+    Status(ExecutionState),
+}
+
+/** A running execute request.
+
+Iterating over the handle yields the request's [events](enum.ExecutionEvent.html) as the kernel
+publishes them, ending once the kernel goes back to `idle`. Messages caused by other requests are
+skipped, and messages for this request that cannot be verified or parsed are yielded as errors. If the client has a [default timeout](../struct.Client.html#method.set_timeout), it
+applies to the whole execution, and a timeout is yielded as the last item.
+
+Dropping the handle stops listening for the request's output, but does not stop the code running
+on the kernel; use [`interrupt`](#method.interrupt) for that.
+*/
+pub struct ExecutionHandle<'a> {
+    client: &'a Client,
+    msg_id: String,
+    events: Receiver<Result<Response>>,
+    replies: Receiver<Result<Response>>,
+    outputs: Vec<Output>,
+    deadline: Option<Deadline>,
+    finished: bool,
+    _request: InFlight<'a>,
+    _listening: StopListening,
+}
+
+impl<'a> ExecutionHandle<'a> {
+    /// Send `wire`, an `execute_request`, and start following its output.
+    pub(crate) fn start(client: &'a Client, wire: WireMessage<Auth>) -> Result<Self> {
+        let msg_id = wire.msg_id()?;
+        let deadline = client.timeout().map(|timeout| Deadline {
+            at: Instant::now() + timeout,
+            timeout,
+        });
+
+        // Listen before sending, so no output can be missed
+        let (tx, events) = mpsc::channel();
+        let listening = client.subscribe_iopub(
+            IoPubSubscription::new()
+                .parent_msg_id(msg_id.clone())
+                .skip_unreadable(),
+            Box::new(move |msg| tx.send(msg.clone()).is_ok()),
+        );
+
+        let (tx, replies) = mpsc::channel();
+        let request = client.shell_router().start(
+            wire,
+            Box::new(move |reply| {
+                let _ = tx.send(reply);
+            }),
+        )?;
+
+        Ok(ExecutionHandle {
+            client,
+            msg_id,
+            events,
+            replies,
+            outputs: Vec::new(),
+            deadline,
+            finished: false,
+            _request: request,
+            _listening: listening,
+        })
+    }
+
+    /// Id of the `execute_request` message.
+    pub fn msg_id(&self) -> &str {
+        &self.msg_id
+    }
+
+    /// Interrupt the kernel, as with [`Client::interrupt`](../struct.Client.html#method.interrupt).
+    pub fn interrupt(&self) -> Result<()> {
+        self.client.interrupt()
+    }
+
+    /** Wait for the request to finish.
+
+    The outcome holds every output of the request, including those already yielded by the
+    iterator. Fails with the first error, including messages for the request that cannot be
+    verified or parsed, as the kernel may have gone idle in one of them.
+    */
+    pub fn wait(mut self) -> Result<ExecutionOutcome> {
+        for event in &mut self {
+            event?;
+        }
+        let reply = match recv(&self.replies, self.deadline.as_ref())?? {
+            Response::Shell(ShellResponse::Execute { content, .. }) => content,
+            other => {
+                return Err(Error::Protocol(format!(
+                    "expected an execute_reply, got {:?}",
+                    other
+                )))
+            }
+        };
+        Ok(ExecutionOutcome {
+            execution_count: reply.execution_count,
+            reply,
+            outputs: std::mem::take(&mut self.outputs),
+        })
+    }
+}
+
+impl<'a> Iterator for ExecutionHandle<'a> {
+    type Item = Result<ExecutionEvent>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.finished {
+            let msg = match recv(&self.events, self.deadline.as_ref()) {
+                Ok(Ok(msg)) => msg,
+                Ok(Err(e)) => return Some(Err(e)),
+                Err(e) => {
+                    self.finished = true;
+                    return Some(Err(e));
+                }
+            };
+            if let Response::IoPub(IoPubResponse::Status { content, .. }) = msg {
+                self.finished = content.execution_state == ExecutionState::Idle;
+                return Some(Ok(ExecutionEvent::Status(content.execution_state)));
+            }
+            if let Some(output) = Output::from_response(&msg) {
+                self.outputs.push(output.clone());
+                return Some(Ok(ExecutionEvent::Output(output)));
+            }
+        }
+        None
+    }
+}
+
+struct Deadline {
+    at: Instant,
+    timeout: Duration,
+}
+
+/// Receive from `rx`, giving up at the deadline.
+fn recv<T>(rx: &Receiver<T>, deadline: Option<&Deadline>) -> Result<T> {
+    match deadline {
+        Some(deadline) => {
+            let remaining = deadline.at.saturating_duration_since(Instant::now());
+            rx.recv_timeout(remaining).map_err(|e| match e {
+                RecvTimeoutError::Timeout => Error::Timeout(deadline.timeout),
+                RecvTimeoutError::Disconnected => Error::KernelDead,
+            })
+        }
+        None => rx.recv().map_err(|_| Error::KernelDead),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kernelspec::InterruptMode;
+    use crate::test_helpers::FakeKernel;
+    use serde_json::json;
+    use std::thread;
+
+    #[test]
+    fn test_streaming_execution() {
+        let kernel = FakeKernel::new();
+        let mut client = kernel.client();
+        client.set_timeout(Some(Duration::from_secs(5)));
+        client.set_interrupt_mode(InterruptMode::Message);
+        kernel.wait_for_subscriber();
+
+        let handle = thread::spawn(move || {
+            let request = kernel.recv(&kernel.shell);
+            let publish =
+                |msg_type, content| kernel.reply(&kernel.iopub, &request, msg_type, content);
+            publish("status", json!({"execution_state": "busy"}));
+            publish("stream", json!({"name": "stdout", "text": "0\n"}));
+
+            // Keep running until interrupted
+            let interrupt = kernel.recv(&kernel.control);
+            kernel.reply(
+                &kernel.control,
+                &interrupt,
+                "interrupt_reply",
+                json!({"status": "ok"}),
+            );
+            let error = json!({
+                "ename": "KeyboardInterrupt",
+                "evalue": "",
+                "traceback": [],
+            });
+            publish("error", error.clone());
+            let mut reply = error;
+            reply["status"] = json!("error");
+            reply["execution_count"] = json!(1);
+            kernel.reply(&kernel.shell, &request, "execute_reply", reply);
+            publish("status", json!({"execution_state": "idle"}));
+        });
+
+        let mut execution = client.start_execute("while True: print(i)").unwrap();
+        match execution.next() {
+            Some(Ok(ExecutionEvent::Status(ExecutionState::Busy))) => {}
+            other => unreachable!("expected a busy status, got {:?}", other),
+        }
+        match execution.next() {
+            Some(Ok(ExecutionEvent::Output(Output::Stream(stream)))) => {
+                assert_eq!(stream.text, "0\n")
+            }
+            other => unreachable!("expected a stream, got {:?}", other),
+        }
+        execution.interrupt().unwrap();
+
+        let outcome = execution.wait().unwrap();
+        handle.join().unwrap();
+        assert!(!outcome.is_ok());
+        assert_eq!(outcome.reply.ename.as_deref(), Some("KeyboardInterrupt"));
+        match &outcome.outputs[..] {
+            [Output::Stream(_), Output::Error(error)] => {
+                assert_eq!(error.ename, "KeyboardInterrupt")
+            }
+            other => unreachable!("unexpected outputs {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_undecodable_output() {
+        let kernel = FakeKernel::new();
+        let mut client = kernel.client();
+        client.set_timeout(Some(Duration::from_secs(5)));
+        kernel.wait_for_subscriber();
+
+        let handle = thread::spawn(move || {
+            let request = kernel.recv(&kernel.shell);
+            let publish =
+                |msg_type, content| kernel.reply(&kernel.iopub, &request, msg_type, content);
+            publish(
+                "execute_result",
+                json!({
+                    "execution_count": 1,
+                    "data": {"text/plain": "{'a': 1}", "application/json": {"a": 1}},
+                    "metadata": {},
+                }),
+            );
+            publish("stream", json!("not a stream"));
+            kernel.reply(
+                &kernel.shell,
+                &request,
+                "execute_reply",
+                json!({"status": "ok", "execution_count": 1}),
+            );
+            publish("status", json!({"execution_state": "idle"}));
+        });
+
+        let mut execution = client.start_execute("{'a': 1}").unwrap();
+        match execution.next() {
+            Some(Ok(ExecutionEvent::Output(Output::ExecuteResult(result)))) => {
+                assert_eq!(result.data["application/json"], json!({"a": 1}))
+            }
+            other => unreachable!("expected a result, got {:?}", other),
+        }
+        match execution.next() {
+            Some(Err(Error::Json { msg_type, .. })) => {
+                assert_eq!(msg_type.as_deref(), Some("stream"))
+            }
+            other => unreachable!("expected a decoding error, got {:?}", other),
+        }
+        match execution.next() {
+            Some(Ok(ExecutionEvent::Status(ExecutionState::Idle))) => {}
+            other => unreachable!("expected an idle status, got {:?}", other),
+        }
+        assert!(execution.wait().unwrap().is_ok());
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_execution_timeout() {
+        let kernel = FakeKernel::new();
+        let mut client = kernel.client();
+        let timeout = Duration::from_millis(100);
+        client.set_timeout(Some(timeout));
+
+        let mut execution = client.start_execute("import time; time.sleep(10)").unwrap();
+        match execution.next() {
+            Some(Err(Error::Timeout(t))) => assert_eq!(t, timeout),
+            other => unreachable!("expected a timeout, got {:?}", other),
+        }
+        assert!(execution.next().is_none());
+    }
+}
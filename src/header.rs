@@ -2,26 +2,49 @@ use crate::errors::Result;
 use chrono::Utc;
 use serde_derive::{Deserialize, Serialize};
 
+/// Header of a message.
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Header {
+    /// Time at which the message was created.
     pub date: String,
+    /// Unique id of the message.
     pub msg_id: String,
+    /// Name of the user who sent the message.
     pub username: String,
+    /// Session id of the sender.
     pub session: String,
+    /// Type of the message, e.g. `execute_request`.
     pub msg_type: String,
+    /// Version of the messaging protocol.
     pub version: String,
 }
 
+/// Identity of a client session, stamped on the header of every message the client sends.
+#[derive(Debug, Clone)]
+pub(crate) struct Session {
+    pub(crate) id: String,
+    pub(crate) username: String,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Session {
+            id: msg_id(),
+            username: "client".to_string(),
+        }
+    }
+}
+
 impl Header {
-    pub fn new<S>(msg_type: S) -> Header
+    pub(crate) fn new<S>(msg_type: S, session: &Session) -> Header
     where
         S: Into<String>,
     {
         Header {
             date: format!("{:?}", Utc::now()),
             msg_id: msg_id(),
-            username: "client".to_string(),
-            session: "".to_string(),
+            username: session.username.clone(),
+            session: session.id.clone(),
             msg_type: msg_type.into(),
             version: "5.0".to_string(),
         }
@@ -44,15 +67,32 @@ mod tests {
 
     #[test]
     fn test_msg_type() {
-        let header = Header::new("test");
+        let header = Header::new("test", &Session::default());
         assert_eq!(header.msg_type, "test");
     }
 
     #[test]
     fn test_uuid() {
         // Ensure the msg_id is a proper uuid
-        let header = Header::new("test");
+        let header = Header::new("test", &Session::default());
         assert_eq!(header.msg_id.len(), 36);
         assert!(header.msg_id.contains("-"));
     }
+
+    #[test]
+    fn test_session() {
+        let session = Session {
+            id: "my-session".to_string(),
+            username: "alice".to_string(),
+        };
+        let first = Header::new("test", &session);
+        let second = Header::new("test", &session);
+        assert_eq!(first.session, "my-session");
+        assert_eq!(first.username, "alice");
+        assert_eq!(first.session, second.session);
+        assert_ne!(first.msg_id, second.msg_id);
+
+        // Each default session is unique
+        assert_ne!(Session::default().id, Session::default().id);
+    }
 }
@@ -0,0 +1,205 @@
+/*! Checking that the kernel is still alive.
+
+The kernel echoes back anything sent to its heartbeat socket. A
+[`HeartbeatMonitor`](struct.HeartbeatMonitor.html) sends a heartbeat at a regular interval and
+reports each outcome as a [`HeartbeatEvent`](enum.HeartbeatEvent.html). After too many missed
+heartbeats in a row, the kernel is reported dead.
+
+```no_run
+# use jupyter_client::{Result, Client};
+use jupyter_client::heartbeat::{HeartbeatEvent, HeartbeatMonitor};
+use std::time::Duration;
+
+# fn main() -> Result<()> {
+let client = Client::existing()?;
+let monitor = HeartbeatMonitor::new()
+    .interval(Duration::from_secs(5))
+    .timeout(Duration::from_secs(2))
+    .failure_threshold(3);
+for event in client.monitor_heartbeat(monitor)? {
+    if event == HeartbeatEvent::Dead {
+        eprintln!("kernel died");
+    }
+}
+# Ok(())
+# }
+```
+*/
+use crate::errors::Result;
+use crate::socket::Socket;
+use log::{debug, warn};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Outcome of a heartbeat.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeartbeatEvent {
+    /// The kernel replied, after the given round trip time.
+    Alive(Duration),
+    /// The kernel did not reply in time.
+    Missed,
+    /// The kernel missed too many heartbeats in a row. This is the last event sent.
+    Dead,
+}
+
+/** Settings for monitoring the kernel heartbeat.
+
+Start monitoring with [`Client::monitor_heartbeat`](../struct.Client.html#method.monitor_heartbeat).
+By default a heartbeat is sent every second, a reply is expected within a second, and the kernel
+is considered dead after 3 missed heartbeats in a row.
+*/
+#[derive(Debug, Clone)]
+pub struct HeartbeatMonitor {
+    interval: Duration,
+    timeout: Duration,
+    failure_threshold: u32,
+}
+
+impl Default for HeartbeatMonitor {
+    fn default() -> Self {
+        HeartbeatMonitor {
+            interval: Duration::from_secs(1),
+            timeout: Duration::from_secs(1),
+            failure_threshold: 3,
+        }
+    }
+}
+
+impl HeartbeatMonitor {
+    /// Create a monitor with the default settings.
+    pub fn new() -> Self {
+        HeartbeatMonitor::default()
+    }
+
+    /// Time between the start of two heartbeats.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// How long to wait for a reply before counting a heartbeat as missed.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Number of heartbeats missed in a row after which the kernel is considered dead. This is
+    /// at least 1.
+    pub fn failure_threshold(mut self, failure_threshold: u32) -> Self {
+        self.failure_threshold = failure_threshold.max(1);
+        self
+    }
+
+    /** Monitor the heartbeat on a background thread, passing each event to `sink`.
+
+    `connect` creates a new heartbeat socket. A REQ socket cannot send again until it has a
+    reply, so the socket is replaced after each missed heartbeat. Monitoring stops after `Dead`,
+    or when `sink` returns `false`.
+    */
+    pub(crate) fn spawn<C, F>(self, connect: C, mut sink: F) -> Result<()>
+    where
+        C: Fn() -> Result<Socket> + Send + 'static,
+        F: FnMut(HeartbeatEvent) -> bool + Send + 'static,
+    {
+        let mut socket = Some(connect()?);
+        thread::spawn(move || {
+            let mut missed = 0;
+            loop {
+                let started = Instant::now();
+                let beat = match socket.take() {
+                    Some(s) => s.heartbeat(self.timeout).map(|rtt| (s, rtt)),
+                    None => connect().and_then(|s| {
+                        let rtt = s.heartbeat(self.timeout)?;
+                        Ok((s, rtt))
+                    }),
+                };
+                let event = match beat {
+                    Ok((s, rtt)) => {
+                        socket = Some(s);
+                        missed = 0;
+                        HeartbeatEvent::Alive(rtt)
+                    }
+                    Err(e) => {
+                        missed += 1;
+                        debug!("missed heartbeat {}: {}", missed, e);
+                        if missed >= self.failure_threshold {
+                            warn!("kernel missed {} heartbeats, assuming it is dead", missed);
+                            HeartbeatEvent::Dead
+                        } else {
+                            HeartbeatEvent::Missed
+                        }
+                    }
+                };
+
+                if !sink(event) || event == HeartbeatEvent::Dead {
+                    break;
+                }
+                thread::sleep(self.interval.saturating_sub(started.elapsed()));
+            }
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::FakeKernel;
+
+    /// Echo heartbeats until `until`.
+    fn answer_until(kernel: &FakeKernel, until: Instant) {
+        loop {
+            let left = until.saturating_duration_since(Instant::now());
+            if left == Duration::from_millis(0) {
+                break;
+            }
+            if kernel
+                .heartbeat
+                .poll(zmq::POLLIN, left.as_millis() as i64)
+                .unwrap()
+                > 0
+            {
+                let ping = kernel.heartbeat.recv_bytes(0).unwrap();
+                kernel.heartbeat.send(ping, 0).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn test_heartbeat_recovers_then_dies() {
+        let kernel = FakeKernel::new();
+        let client = kernel.client();
+        let monitor = HeartbeatMonitor::new()
+            .interval(Duration::from_millis(20))
+            .timeout(Duration::from_millis(50))
+            .failure_threshold(8);
+        let events = client.monitor_heartbeat(monitor).unwrap();
+
+        // Answer, then go quiet for a while, then answer again before dying
+        let handle = thread::spawn(move || {
+            let start = Instant::now();
+            answer_until(&kernel, start + Duration::from_millis(100));
+            thread::sleep(Duration::from_millis(150));
+            answer_until(&kernel, Instant::now() + Duration::from_millis(200));
+        });
+
+        let events: Vec<_> = events.iter().collect();
+        handle.join().unwrap();
+        let first_missed = events
+            .iter()
+            .position(|e| *e == HeartbeatEvent::Missed)
+            .unwrap();
+        assert!(matches!(events[0], HeartbeatEvent::Alive(_)));
+        assert!(events[first_missed..]
+            .iter()
+            .any(|e| matches!(e, HeartbeatEvent::Alive(_))));
+        assert_eq!(events.last(), Some(&HeartbeatEvent::Dead));
+        let trailing_missed = events
+            .iter()
+            .rev()
+            .skip(1)
+            .take_while(|e| **e == HeartbeatEvent::Missed)
+            .count();
+        assert_eq!(trailing_missed, 7);
+    }
+}
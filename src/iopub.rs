@@ -0,0 +1,608 @@
+/*! Subscriptions to IOPub messages.
+
+Everything the kernel publishes, such as output, errors and status changes, is sent on the IOPub
+channel. A single background thread owns the IOPub socket and hands each message to every
+subscriber, so subscribers and running executions all see every message. Messages that cannot be
+received or parsed are passed on as errors.
+
+An [`IoPubSubscription`](struct.IoPubSubscription.html) narrows down the messages a subscriber
+receives. Messages are matched on their topic, header and parent header, and only parsed in full
+if some subscriber wants them, so ignoring large messages such as plots is cheap.
+
+```no_run
+# use jupyter_client::{Result, Client};
+use jupyter_client::iopub::IoPubSubscription;
+# fn main() -> Result<()> {
+let client = Client::existing()?;
+let errors = client.iopub_subscribe_with(IoPubSubscription::new().msg_type("error"))?;
+for msg in errors {
+    println!("{:?}", msg?);
+}
+# Ok(())
+# }
+```
+
+The thread only runs while someone is listening. It stops when the last subscriber goes away, and
+is started again by the next subscription. Dropping the client stops it for good.
+*/
+use crate::background::{self, lock, SHUTDOWN};
+use crate::errors::Result;
+use crate::responses::Response;
+use crate::signatures::Auth;
+use crate::socket::Socket;
+use crate::wire::{WireMessage, DELIMITER};
+use log::{debug, error, trace};
+use serde_derive::Deserialize;
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::mpsc::{Receiver, RecvError, RecvTimeoutError, TryRecvError};
+use std::sync::{Arc, Mutex, Weak};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/** Which IOPub messages a subscriber receives.
+
+A new subscription receives every message. Each condition added narrows it down, and a message
+must meet all of them. Conditions of the same kind are alternatives: a subscription to the
+`stream` and `error` message types receives both.
+*/
+#[derive(Debug, Clone, Default)]
+pub struct IoPubSubscription {
+    msg_types: HashSet<String>,
+    parent_sessions: HashSet<String>,
+    parent_msg_ids: HashSet<String>,
+    topics: Vec<Vec<u8>>,
+    /// Whether messages whose envelope cannot be read are skipped, instead of passed on as errors.
+    skip_unreadable: bool,
+}
+
+impl IoPubSubscription {
+    /// Subscribe to every message.
+    pub fn new() -> Self {
+        IoPubSubscription::default()
+    }
+
+    /// Only receive messages of this type, e.g. `"stream"`.
+    pub fn msg_type<S>(mut self, msg_type: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.msg_types.insert(msg_type.into());
+        self
+    }
+
+    /// Only receive messages caused by a request from this session, such as a client's
+    /// [`session_id`](../struct.Client.html#method.session_id).
+    pub fn parent_session<S>(mut self, session: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.parent_sessions.insert(session.into());
+        self
+    }
+
+    /// Only receive messages caused by the request with this `msg_id`.
+    pub fn parent_msg_id<S>(mut self, msg_id: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.parent_msg_ids.insert(msg_id.into());
+        self
+    }
+
+    /// Only receive messages whose ZMQ topic starts with `prefix`. Kernels usually publish with
+    /// a topic such as `kernel.<id>.stream`.
+    pub fn topic_prefix<T>(mut self, prefix: T) -> Self
+    where
+        T: Into<Vec<u8>>,
+    {
+        self.topics.push(prefix.into());
+        self
+    }
+
+    /// Only receive errors for messages known to match, for subscribers that cannot tell unrelated
+    /// errors apart.
+    pub(crate) fn skip_unreadable(mut self) -> Self {
+        self.skip_unreadable = true;
+        self
+    }
+
+    fn matches(&self, envelope: &Envelope) -> bool {
+        let parent = envelope.parent.as_ref();
+        (self.topics.is_empty() || self.topics.iter().any(|t| envelope.topic.starts_with(t)))
+            && (self.msg_types.is_empty() || self.msg_types.contains(&envelope.header.msg_type))
+            && (self.parent_sessions.is_empty()
+                || parent.is_some_and(|p| self.parent_sessions.contains(&p.session)))
+            && (self.parent_msg_ids.is_empty()
+                || parent.is_some_and(|p| self.parent_msg_ids.contains(&p.msg_id)))
+    }
+}
+
+/// Header fields used to filter messages.
+#[derive(Deserialize)]
+struct EnvelopeHeader {
+    msg_type: String,
+}
+
+/// Parent header fields used to filter messages.
+#[derive(Deserialize)]
+struct EnvelopeParent {
+    msg_id: String,
+    session: String,
+}
+
+/// The parts of a message that subscriptions match on, read without parsing its content.
+struct Envelope {
+    topic: Vec<u8>,
+    header: EnvelopeHeader,
+    parent: Option<EnvelopeParent>,
+}
+
+impl Envelope {
+    /// Read the envelope of raw message frames, or `None` if they are malformed. Signatures are
+    /// checked later, for the messages that are wanted.
+    fn read(frames: &[Vec<u8>]) -> Option<Envelope> {
+        let delimiter = frames.iter().position(|f| f.as_slice() == DELIMITER)?;
+        let header = serde_json::from_slice(frames.get(delimiter + 2)?).ok()?;
+        // An empty parent header is valid, and matches no parent
+        let parent = serde_json::from_slice(frames.get(delimiter + 3)?).ok();
+        Some(Envelope {
+            topic: frames[..delimiter].first().cloned().unwrap_or_default(),
+            header,
+            parent,
+        })
+    }
+}
+
+/** Receives the IOPub messages of one subscription.
+
+Messages are received as from a
+[`Receiver`](https://doc.rust-lang.org/std/sync/mpsc/struct.Receiver.html), and iterating blocks
+until the next message. Dropping the receiver unsubscribes it. The channel is closed when the
+client is dropped.
+*/
+#[derive(Debug)]
+pub struct IoPubReceiver {
+    rx: Receiver<Result<Response>>,
+    _listening: StopListening,
+}
+
+impl IoPubReceiver {
+    pub(crate) fn new(rx: Receiver<Result<Response>>, listening: StopListening) -> Self {
+        IoPubReceiver {
+            rx,
+            _listening: listening,
+        }
+    }
+
+    /// Wait for the next message.
+    pub fn recv(&self) -> std::result::Result<Result<Response>, RecvError> {
+        self.rx.recv()
+    }
+
+    /// Return the next message if one has already arrived.
+    pub fn try_recv(&self) -> std::result::Result<Result<Response>, TryRecvError> {
+        self.rx.try_recv()
+    }
+
+    /// Wait for the next message for at most `timeout`.
+    pub fn recv_timeout(
+        &self,
+        timeout: Duration,
+    ) -> std::result::Result<Result<Response>, RecvTimeoutError> {
+        self.rx.recv_timeout(timeout)
+    }
+}
+
+impl Iterator for IoPubReceiver {
+    type Item = Result<Response>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.rx.recv().ok()
+    }
+}
+
+/// Callback receiving each IOPub message. Returning `false` unsubscribes it.
+pub(crate) type Listener = Box<dyn FnMut(&Result<Response>) -> bool + Send>;
+
+/// Frame asking the background thread to stop if nobody is listening any more.
+static WAKE: &[u8] = b"wake";
+
+/// Sockets owned by the background thread while it runs.
+struct Sockets {
+    iopub: Socket,
+    shutdown: zmq::Socket,
+}
+
+struct Subscriber {
+    id: u64,
+    subscription: IoPubSubscription,
+    listener: Listener,
+}
+
+struct State {
+    subscribers: Vec<Subscriber>,
+    next_id: u64,
+    /// The sockets, while no thread is running.
+    idle: Option<Sockets>,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    /// Sends `SHUTDOWN` or `WAKE` to the background thread.
+    wake: Mutex<zmq::Socket>,
+}
+
+pub(crate) struct IoPubDispatcher {
+    shared: Arc<Shared>,
+    thread: Mutex<Option<JoinHandle<()>>>,
+    auth: Auth,
+}
+
+/// Removes a subscriber when dropped, so it is forgotten even if no message it wants arrives.
+pub(crate) struct StopListening {
+    shared: Weak<Shared>,
+    id: u64,
+}
+
+impl fmt::Debug for StopListening {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StopListening")
+            .field("id", &self.id)
+            .finish()
+    }
+}
+
+impl Drop for StopListening {
+    fn drop(&mut self) {
+        // The client is already gone
+        let shared = match self.shared.upgrade() {
+            Some(shared) => shared,
+            None => return,
+        };
+        let stop_thread = {
+            let mut state = lock(&shared.state);
+            state.subscribers.retain(|s| s.id != self.id);
+            state.subscribers.is_empty() && state.idle.is_none()
+        };
+        if stop_thread {
+            // The thread may have stopped in the meantime
+            let _ = background::send(&lock(&shared.wake), [WAKE]);
+        }
+    }
+}
+
+impl IoPubDispatcher {
+    /// Dispatch messages received on `socket`, which must be a SUB socket.
+    pub(crate) fn new(ctx: &zmq::Context, socket: Socket, auth: Auth) -> Result<IoPubDispatcher> {
+        let (incoming, shutdown) = background::pair(ctx, "iopub")?;
+
+        let state = State {
+            subscribers: Vec::new(),
+            next_id: 0,
+            idle: Some(Sockets {
+                iopub: socket,
+                shutdown: incoming,
+            }),
+        };
+        Ok(IoPubDispatcher {
+            shared: Arc::new(Shared {
+                state: Mutex::new(state),
+                wake: Mutex::new(shutdown),
+            }),
+            thread: Mutex::new(None),
+            auth,
+        })
+    }
+
+    /// Pass every message received from now on that matches `subscription` to `listener`,
+    /// until it returns `false` or the returned guard is dropped.
+    pub(crate) fn subscribe(
+        &self,
+        subscription: IoPubSubscription,
+        listener: Listener,
+    ) -> StopListening {
+        let mut state = lock(&self.shared.state);
+        let id = state.next_id;
+        state.next_id += 1;
+        state.subscribers.push(Subscriber {
+            id,
+            subscription,
+            listener,
+        });
+        if let Some(sockets) = state.idle.take() {
+            // Messages that arrived while nobody was listening are stale
+            discard_pending(&sockets.iopub);
+            let mut thread = lock(&self.thread);
+            // A previous thread has already handed back the sockets, so it is about to finish
+            if let Some(previous) = thread.take() {
+                let _ = previous.join();
+            }
+            let auth = self.auth.clone();
+            let shared = self.shared.clone();
+            *thread = Some(thread::spawn(move || dispatch(sockets, auth, shared)));
+        }
+        StopListening {
+            shared: Arc::downgrade(&self.shared),
+            id,
+        }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn subscriber_count(&self) -> usize {
+        lock(&self.shared.state).subscribers.len()
+    }
+}
+
+impl Drop for IoPubDispatcher {
+    fn drop(&mut self) {
+        let mut thread = lock(&self.thread);
+        if let Some(thread) = thread.take() {
+            if background::send(&lock(&self.shared.wake), [SHUTDOWN]).is_ok() {
+                let _ = thread.join();
+            }
+        }
+    }
+}
+
+fn discard_pending(socket: &Socket) {
+    while let Ok(frames) = socket.0.recv_multipart(zmq::DONTWAIT) {
+        debug!("discarding IOPub message with {} frames", frames.len());
+    }
+}
+
+fn dispatch(sockets: Sockets, auth: Auth, shared: Arc<Shared>) {
+    loop {
+        let (message_ready, shutdown_ready) =
+            match background::poll(&sockets.iopub.0, &sockets.shutdown) {
+                Ok(ready) => ready,
+                Err(e) => {
+                    // Current subscribers are told and dropped, and the next one starts over
+                    error!("polling failed, no longer receiving IOPub messages: {}", e);
+                    let mut state = lock(&shared.state);
+                    let msg = Err(e.into());
+                    for mut subscriber in state.subscribers.drain(..) {
+                        (subscriber.listener)(&msg);
+                    }
+                    state.idle = Some(sockets);
+                    return;
+                }
+            };
+
+        if shutdown_ready {
+            match sockets.shutdown.recv_bytes(0) {
+                Ok(ref frame) if frame.as_slice() == WAKE => {
+                    let mut state = lock(&shared.state);
+                    if state.subscribers.is_empty() {
+                        debug!("no IOPub subscribers left, stopping");
+                        state.idle = Some(sockets);
+                        return;
+                    }
+                }
+                _ => return,
+            }
+        }
+
+        if message_ready {
+            let frames = sockets.iopub.0.recv_multipart(0);
+            let mut state = lock(&shared.state);
+            // Messages whose envelope cannot be read go to everyone, as errors
+            let envelope = frames.as_ref().ok().and_then(|f| Envelope::read(f));
+            let wanted: Vec<bool> = state
+                .subscribers
+                .iter()
+                .map(|s| match &envelope {
+                    Some(envelope) => s.subscription.matches(envelope),
+                    None => !s.subscription.skip_unreadable,
+                })
+                .collect();
+            if !wanted.contains(&true) {
+                trace!("no subscriber wants this IOPub message");
+                continue;
+            }
+
+            let msg = frames
+                .map_err(Into::into)
+                .and_then(|frames| WireMessage::from_raw_response(frames, auth.clone()))
+                .and_then(|wire| wire.into_response());
+            let mut wanted = wanted.into_iter();
+            state.subscribers.retain_mut(|subscriber| {
+                !wanted.next().unwrap_or(false) || (subscriber.listener)(&msg)
+            });
+            if state.subscribers.is_empty() {
+                debug!("no IOPub subscribers left, stopping");
+                state.idle = Some(sockets);
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::Command;
+    use crate::errors::Error;
+    use crate::header::Session;
+    use crate::responses::IoPubResponse;
+    use crate::test_helpers::FakeKernel;
+    use serde_json::json;
+
+    fn stream(kernel: &FakeKernel, text: &str) {
+        kernel.publish("stream", json!({"name": "stdout", "text": text}));
+    }
+
+    fn recv_text(rx: &IoPubReceiver) -> String {
+        match rx.recv_timeout(Duration::from_secs(5)) {
+            Ok(Ok(Response::IoPub(IoPubResponse::Stream { content, .. }))) => content.text,
+            other => unreachable!("expected a stream message, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_broadcast_with_errors() {
+        let kernel = FakeKernel::new();
+        let client = kernel.client();
+        kernel.wait_for_subscriber();
+        let first = client.iopub_subscribe().unwrap();
+        let second = client.iopub_subscribe().unwrap();
+
+        kernel
+            .iopub
+            .send_multipart(
+                vec![
+                    b"stream".to_vec(),
+                    b"<IDS|MSG>".to_vec(),
+                    b"bad signature".to_vec(),
+                    b"{}".to_vec(),
+                    b"{}".to_vec(),
+                    b"{}".to_vec(),
+                    b"{}".to_vec(),
+                ],
+                0,
+            )
+            .unwrap();
+        stream(&kernel, "hello");
+        for rx in &[&first, &second] {
+            match rx.recv_timeout(Duration::from_secs(5)) {
+                Ok(Err(Error::SignatureMismatch)) => {}
+                other => unreachable!("expected a signature error, got {:?}", other),
+            }
+            assert_eq!(recv_text(rx), "hello");
+        }
+
+        drop(client);
+        assert!(first.recv().is_err());
+        assert!(second.recv().is_err());
+    }
+
+    #[test]
+    fn test_filtered_subscriptions() {
+        let kernel = FakeKernel::new();
+        let client = kernel.client();
+        kernel.wait_for_subscriber();
+        let request = Command::KernelInfo
+            .into_wire(kernel.auth.clone(), &Session::default())
+            .unwrap();
+
+        let all = client.iopub_subscribe().unwrap();
+        let errors = client
+            .iopub_subscribe_with(IoPubSubscription::new().msg_type("error"))
+            .unwrap();
+        let streams = client
+            .iopub_subscribe_with(IoPubSubscription::new().topic_prefix("stream"))
+            .unwrap();
+        let children = client
+            .iopub_subscribe_with(IoPubSubscription::new().parent_msg_id(request.msg_id().unwrap()))
+            .unwrap();
+
+        // Content that cannot be parsed is only an error for the subscribers that want it
+        kernel.publish("display_data", json!("not a display"));
+        stream(&kernel, "out");
+        kernel.reply(
+            &kernel.iopub,
+            &request,
+            "error",
+            json!({"ename": "ValueError", "evalue": "", "traceback": []}),
+        );
+
+        match all.recv_timeout(Duration::from_secs(5)) {
+            Ok(Err(Error::Json { msg_type, .. })) => {
+                assert_eq!(msg_type.as_deref(), Some("display_data"))
+            }
+            other => unreachable!("expected a decoding error, got {:?}", other),
+        }
+        assert_eq!(recv_text(&all), "out");
+        for rx in &[&all, &errors, &children] {
+            match rx.recv_timeout(Duration::from_secs(5)) {
+                Ok(Ok(Response::IoPub(IoPubResponse::Error { content, .. }))) => {
+                    assert_eq!(content.ename, "ValueError")
+                }
+                other => unreachable!("expected an error message, got {:?}", other),
+            }
+        }
+        assert_eq!(recv_text(&streams), "out");
+        for rx in &[&all, &errors, &streams, &children] {
+            assert!(rx.try_recv().is_err());
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_dispatch_survives_signals() {
+        let ctx = zmq::Context::new();
+        let socket = Socket(ctx.socket(zmq::SUB).unwrap());
+        let auth = Auth::new("hmac-sha256", b"secret").unwrap();
+        let dispatcher = IoPubDispatcher::new(&ctx, socket, auth).unwrap();
+        let (tx, rx) = std::sync::mpsc::channel();
+        let _listening = dispatcher.subscribe(
+            IoPubSubscription::new(),
+            Box::new(move |msg: &Result<Response>| tx.send(msg.is_ok()).is_ok()),
+        );
+
+        crate::test_helpers::signal_thread(lock(&dispatcher.thread).as_ref().unwrap());
+        assert!(!lock(&dispatcher.thread).as_ref().unwrap().is_finished());
+        assert!(rx.try_recv().is_err());
+        assert_eq!(dispatcher.subscriber_count(), 1);
+    }
+
+    #[test]
+    fn test_subscription_matches() {
+        let envelope = Envelope {
+            topic: b"kernel.1234.stream".to_vec(),
+            header: EnvelopeHeader {
+                msg_type: "stream".to_string(),
+            },
+            parent: Some(EnvelopeParent {
+                msg_id: "abc".to_string(),
+                session: "session".to_string(),
+            }),
+        };
+        assert!(IoPubSubscription::new().matches(&envelope));
+        assert!(IoPubSubscription::new()
+            .msg_type("error")
+            .msg_type("stream")
+            .topic_prefix("kernel.")
+            .parent_session("session")
+            .parent_msg_id("abc")
+            .matches(&envelope));
+        assert!(!IoPubSubscription::new()
+            .msg_type("error")
+            .matches(&envelope));
+        assert!(!IoPubSubscription::new()
+            .topic_prefix("stream")
+            .matches(&envelope));
+        assert!(!IoPubSubscription::new()
+            .parent_session("other")
+            .matches(&envelope));
+
+        let orphan = Envelope {
+            parent: None,
+            ..envelope
+        };
+        assert!(!IoPubSubscription::new()
+            .parent_msg_id("abc")
+            .matches(&orphan));
+    }
+
+    #[test]
+    fn test_resubscribe_after_receivers_dropped() {
+        let kernel = FakeKernel::new();
+        let client = kernel.client();
+        kernel.wait_for_subscriber();
+
+        let rx = client.iopub_subscribe().unwrap();
+        stream(&kernel, "first");
+        assert_eq!(recv_text(&rx), "first");
+        drop(rx);
+
+        // The dispatcher notices nobody is listening, and published messages are then dropped
+        stream(&kernel, "unheard");
+        stream(&kernel, "stale");
+        std::thread::sleep(Duration::from_millis(100));
+
+        let rx = client.iopub_subscribe().unwrap();
+        stream(&kernel, "second");
+        assert_eq!(recv_text(&rx), "second");
+    }
+}
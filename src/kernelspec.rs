@@ -0,0 +1,609 @@
+/*! Kernel specifications.
+
+A kernelspec describes how to start a kernel, and how the client should talk to it. Each one is a
+directory holding a `kernel.json` file and optional resources such as logos, found in the
+`kernels` subdirectory of the Jupyter data directories. The directory name is the kernel name.
+
+```no_run
+# use jupyter_client::Result;
+use jupyter_client::kernelspec::KernelSpecManager;
+# fn main() -> Result<()> {
+let manager = KernelSpecManager::new()?;
+for (name, spec) in manager.get_all_specs()? {
+    println!("{}: {}", name, spec.display_name);
+}
+# Ok(())
+# }
+```
+*/
+use crate::errors::{Error, Result};
+use crate::paths::{jupyter_data_dir, jupyter_path, system_jupyter_path};
+use log::{info, warn};
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+/// Name of the file describing a kernel, in a kernelspec directory.
+static KERNEL_JSON: &str = "kernel.json";
+
+/** How a kernel expects to be interrupted.
+
+This is the `interrupt_mode` field of a kernelspec.
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum InterruptMode {
+    /// Send `SIGINT` to the kernel process. This is the default.
+    #[default]
+    Signal,
+    /// Send an `interrupt_request` message on the control channel.
+    Message,
+}
+
+/** How to start a kernel, read from its `kernel.json`.
+
+See the [Jupyter documentation][kernelspecs] for the meaning of each field.
+
+[kernelspecs]: https://jupyter-client.readthedocs.io/en/stable/kernels.html#kernel-specs
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct KernelSpec {
+    /// Command line to start the kernel. `{connection_file}` is replaced by the path of the
+    /// connection file, and `{resource_dir}` by the kernelspec directory.
+    pub argv: Vec<String>,
+    /// Name shown in user interfaces.
+    pub display_name: String,
+    /// Programming language of the kernel.
+    #[serde(default)]
+    pub language: String,
+    /// How the kernel expects to be interrupted.
+    #[serde(default)]
+    pub interrupt_mode: InterruptMode,
+    /// Environment variables set for the kernel process.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub env: HashMap<String, String>,
+    /// Extra information for clients.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub metadata: HashMap<String, Value>,
+    /// Directory the kernelspec was read from, if any.
+    #[serde(skip)]
+    pub resource_dir: Option<PathBuf>,
+}
+
+impl KernelSpec {
+    /** Read the kernelspec in the directory `resource_dir`.
+
+    The `kernel.json` file is checked against the kernelspec format first, and every problem
+    found is reported in an `Error::InvalidKernelSpec`.
+    */
+    pub fn from_dir<P>(resource_dir: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let resource_dir = resource_dir.as_ref();
+        let path = resource_dir.join(KERNEL_JSON);
+        let invalid = |problems| Error::InvalidKernelSpec {
+            path: path.clone(),
+            problems,
+        };
+        let value: Value = serde_json::from_reader(File::open(&path)?)
+            .map_err(|e| invalid(vec![format!("not valid JSON: {}", e)]))?;
+        let problems = validate(&value);
+        if !problems.is_empty() {
+            return Err(invalid(problems));
+        }
+        let mut spec: KernelSpec =
+            serde_json::from_value(value).map_err(|e| invalid(vec![e.to_string()]))?;
+        spec.resource_dir = Some(resource_dir.to_path_buf());
+        Ok(spec)
+    }
+}
+
+/// Check the contents of a `kernel.json` file, returning a description of each problem.
+fn validate(kernel_json: &Value) -> Vec<String> {
+    let spec = match kernel_json.as_object() {
+        Some(spec) => spec,
+        None => return vec!["expected a JSON object".to_string()],
+    };
+    let mut problems = Vec::new();
+
+    match spec.get("argv") {
+        None => problems.push("`argv` is required".to_string()),
+        Some(Value::Array(argv)) if !argv.is_empty() && argv.iter().all(Value::is_string) => {}
+        Some(_) => problems.push("`argv` must be a non-empty list of strings".to_string()),
+    }
+    match spec.get("display_name") {
+        None => problems.push("`display_name` is required".to_string()),
+        Some(Value::String(_)) => {}
+        Some(_) => problems.push("`display_name` must be a string".to_string()),
+    }
+    if let Some(language) = spec.get("language") {
+        if !language.is_string() {
+            problems.push("`language` must be a string".to_string());
+        }
+    }
+    if let Some(mode) = spec.get("interrupt_mode") {
+        if mode != "signal" && mode != "message" {
+            problems.push(format!(
+                "`interrupt_mode` must be \"signal\" or \"message\", not {}",
+                mode
+            ));
+        }
+    }
+    match spec.get("env") {
+        None => {}
+        Some(Value::Object(env)) => {
+            for (name, value) in env {
+                if !value.is_string() {
+                    problems.push(format!("`env.{}` must be a string", name));
+                }
+            }
+        }
+        Some(_) => problems.push("`env` must be an object".to_string()),
+    }
+    if let Some(metadata) = spec.get("metadata") {
+        if !metadata.is_object() {
+            problems.push("`metadata` must be an object".to_string());
+        }
+    }
+    problems
+}
+
+/// Where to install a kernelspec.
+#[derive(Debug, Clone, PartialEq)]
+pub enum InstallLocation {
+    /// The current user's Jupyter data directory.
+    User,
+    /// The first system-wide Jupyter data directory, e.g. `/usr/local/share/jupyter`.
+    System,
+    /// The `share/jupyter` directory under a prefix, such as a virtual environment.
+    Prefix(PathBuf),
+}
+
+impl InstallLocation {
+    /// Directory holding the kernelspecs of this location.
+    pub fn kernel_dir(&self) -> Result<PathBuf> {
+        let data_dir = match self {
+            InstallLocation::User => jupyter_data_dir()?,
+            InstallLocation::System => {
+                system_jupyter_path().into_iter().next().ok_or_else(|| {
+                    Error::Config("no system Jupyter directory on this platform".to_string())
+                })?
+            }
+            InstallLocation::Prefix(prefix) => prefix.join("share").join("jupyter"),
+        };
+        Ok(data_dir.join("kernels"))
+    }
+}
+
+/** Finds the kernelspecs installed on this machine.
+
+Kernelspecs are looked up in the `kernels` subdirectory of each Jupyter data directory: those in
+`JUPYTER_PATH`, then the user data directory, then the system directories. As in Python's
+`jupyter_client`, kernel names are case insensitive, and when several directories hold a kernel
+with the same name, the first one wins.
+*/
+#[derive(Debug, Clone)]
+pub struct KernelSpecManager {
+    kernel_dirs: Vec<PathBuf>,
+}
+
+impl KernelSpecManager {
+    /// Look for kernelspecs in the standard Jupyter directories, as listed by
+    /// [`jupyter_path`](../paths/fn.jupyter_path.html).
+    pub fn new() -> Result<Self> {
+        Ok(KernelSpecManager::with_kernel_dirs(
+            jupyter_path()?
+                .into_iter()
+                .map(|p| p.join("kernels"))
+                .collect(),
+        ))
+    }
+
+    /// Look for kernelspecs in the given directories, highest priority first.
+    pub fn with_kernel_dirs(kernel_dirs: Vec<PathBuf>) -> Self {
+        KernelSpecManager { kernel_dirs }
+    }
+
+    /// Directories searched for kernelspecs, highest priority first.
+    pub fn kernel_dirs(&self) -> &[PathBuf] {
+        &self.kernel_dirs
+    }
+
+    /// Names of the installed kernels, with the directory of each kernelspec.
+    pub fn find_kernel_specs(&self) -> Result<BTreeMap<String, PathBuf>> {
+        let mut specs = BTreeMap::new();
+        for kernel_dir in &self.kernel_dirs {
+            for (name, path) in list_kernels_in(kernel_dir)? {
+                specs.entry(name).or_insert(path);
+            }
+        }
+        Ok(specs)
+    }
+
+    /// Read the kernelspec of the kernel called `name`.
+    pub fn get_kernel_spec(&self, name: &str) -> Result<KernelSpec> {
+        let name = name.to_lowercase();
+        match self.find_kernel_specs()?.get(&name) {
+            Some(resource_dir) => KernelSpec::from_dir(resource_dir),
+            None => Err(Error::NoSuchKernel(name)),
+        }
+    }
+
+    /// Read every installed kernelspec. Kernelspecs that cannot be read are skipped with a
+    /// warning.
+    pub fn get_all_specs(&self) -> Result<BTreeMap<String, KernelSpec>> {
+        let mut specs = BTreeMap::new();
+        for (name, resource_dir) in self.find_kernel_specs()? {
+            match KernelSpec::from_dir(&resource_dir) {
+                Ok(spec) => {
+                    specs.insert(name, spec);
+                }
+                Err(e) => warn!("skipping kernelspec in {}: {}", resource_dir.display(), e),
+            }
+        }
+        Ok(specs)
+    }
+}
+
+impl KernelSpecManager {
+    /** Install the kernelspec in `source_dir`, with its resources, and return where it went.
+
+    The kernel is named after `source_dir` unless `kernel_name` is given. Its `kernel.json` is
+    validated before anything is copied. An existing kernelspec of the same name in the same
+    location is only overwritten if `replace` is set, in which case it is swapped for the new
+    one once the copy is complete.
+    */
+    pub fn install_kernel_spec<P>(
+        &self,
+        source_dir: P,
+        kernel_name: Option<&str>,
+        location: &InstallLocation,
+        replace: bool,
+    ) -> Result<PathBuf>
+    where
+        P: AsRef<Path>,
+    {
+        let source_dir = source_dir.as_ref();
+        let kernel_name = match kernel_name {
+            Some(name) => name.to_lowercase(),
+            None => source_dir
+                .file_name()
+                .map(|name| name.to_string_lossy().to_lowercase())
+                .unwrap_or_default(),
+        };
+        if !is_valid_kernel_name(&kernel_name) {
+            return Err(Error::InvalidOperation(format!(
+                "invalid kernel name {:?}, names may only contain ASCII letters, numbers, '.', \
+                 '_' and '-'",
+                kernel_name
+            )));
+        }
+        KernelSpec::from_dir(source_dir)?;
+
+        let kernel_dir = location.kernel_dir()?;
+        let destination = kernel_dir.join(&kernel_name);
+        if destination.exists() && !replace {
+            return Err(Error::InvalidOperation(format!(
+                "kernelspec {} is already installed in {}",
+                kernel_name,
+                destination.display()
+            )));
+        }
+
+        fs::create_dir_all(&kernel_dir)?;
+        let staging = kernel_dir.join(format!(".{}-{}", kernel_name, uuid::Uuid::new_v4()));
+        let installed =
+            copy_dir(source_dir, &staging).and_then(|()| move_into_place(&staging, &destination));
+        if let Err(e) = installed {
+            let _ = fs::remove_dir_all(&staging);
+            return Err(e.into());
+        }
+        info!(
+            "installed kernelspec {} in {}",
+            kernel_name,
+            destination.display()
+        );
+        Ok(destination)
+    }
+
+    /// Remove the kernelspec of the kernel called `name`, and return the directory it was in.
+    /// Only the kernelspec that [`get_kernel_spec`](#method.get_kernel_spec) would find is
+    /// removed.
+    pub fn remove_kernel_spec(&self, name: &str) -> Result<PathBuf> {
+        let name = name.to_lowercase();
+        let resource_dir = self
+            .find_kernel_specs()?
+            .remove(&name)
+            .ok_or(Error::NoSuchKernel(name))?;
+        fs::remove_dir_all(&resource_dir)?;
+        info!("removed kernelspec {}", resource_dir.display());
+        Ok(resource_dir)
+    }
+}
+
+/// Copy the directory `from` to the new directory `to`, with everything in it.
+fn copy_dir(from: &Path, to: &Path) -> std::io::Result<()> {
+    fs::create_dir(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.path().is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), target)?;
+        }
+    }
+    Ok(())
+}
+
+/// Rename the directory `from` to `to`. An existing `to` is moved aside first, and only deleted
+/// once `from` is in place, so it is restored if the rename fails.
+fn move_into_place(from: &Path, to: &Path) -> std::io::Result<()> {
+    if !to.exists() {
+        return fs::rename(from, to);
+    }
+    let name = to.file_name().unwrap_or_default().to_string_lossy();
+    let previous = to.with_file_name(format!(".{}-old-{}", name, uuid::Uuid::new_v4()));
+    fs::rename(to, &previous)?;
+    if let Err(e) = fs::rename(from, to) {
+        if let Err(restore) = fs::rename(&previous, to) {
+            warn!(
+                "cannot restore kernelspec {} from {}: {}",
+                to.display(),
+                previous.display(),
+                restore
+            );
+        }
+        return Err(e);
+    }
+    if let Err(e) = fs::remove_dir_all(&previous) {
+        warn!("cannot remove old kernelspec {}: {}", previous.display(), e);
+    }
+    Ok(())
+}
+
+/// Whether `name` only uses the characters allowed in kernel names.
+fn is_valid_kernel_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '_' || c == '-')
+}
+
+/// Kernelspec directories directly inside `dir`, by lowercase name. A missing `dir` has none.
+fn list_kernels_in(dir: &Path) -> Result<Vec<(String, PathBuf)>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(ref e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut kernels = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        if !path.join(KERNEL_JSON).is_file() {
+            continue;
+        }
+        let name = match path.file_name() {
+            Some(name) => name.to_string_lossy().to_lowercase(),
+            None => continue,
+        };
+        if !is_valid_kernel_name(&name) {
+            warn!(
+                "invalid kernelspec directory name, names may only contain ASCII letters, \
+                 numbers, '.', '_' and '-': {}",
+                path.display()
+            );
+        }
+        kernels.push((name, path));
+    }
+    Ok(kernels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::TempDir;
+    use serde_json::json;
+
+    fn write_spec(dir: &Path, name: &str, spec: Value) {
+        let dir = dir.join(name);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(KERNEL_JSON), spec.to_string()).unwrap();
+    }
+
+    #[test]
+    fn test_kernel_spec_parsing() {
+        let spec: KernelSpec = serde_json::from_value(json!({
+            "argv": ["python3", "-m", "ipykernel_launcher", "-f", "{connection_file}"],
+            "display_name": "Python 3",
+            "language": "python",
+            "interrupt_mode": "message",
+            "env": {"PYTHONUNBUFFERED": "1"},
+            "metadata": {"debugger": true},
+        }))
+        .unwrap();
+        assert_eq!(spec.argv[4], "{connection_file}");
+        assert_eq!(spec.display_name, "Python 3");
+        assert_eq!(spec.language, "python");
+        assert_eq!(spec.interrupt_mode, InterruptMode::Message);
+        assert_eq!(spec.env["PYTHONUNBUFFERED"], "1");
+        assert_eq!(spec.metadata["debugger"], json!(true));
+
+        let spec: KernelSpec =
+            serde_json::from_value(json!({"argv": ["ir"], "display_name": "R"})).unwrap();
+        assert_eq!(spec.interrupt_mode, InterruptMode::Signal);
+        assert!(spec.env.is_empty());
+    }
+
+    #[test]
+    fn test_kernel_spec_precedence() {
+        let first = TempDir::new();
+        let second = TempDir::new();
+        write_spec(
+            &first.0,
+            "Python3",
+            json!({"argv": ["first"], "display_name": "First"}),
+        );
+        write_spec(
+            &second.0,
+            "python3",
+            json!({"argv": ["second"], "display_name": "Second"}),
+        );
+        write_spec(&second.0, "ir", json!({"argv": ["R"], "display_name": "R"}));
+        write_spec(&second.0, "broken", json!({"display_name": "No argv"}));
+        fs::create_dir_all(second.0.join("empty")).unwrap();
+
+        let missing = first.0.join("missing");
+        let manager =
+            KernelSpecManager::with_kernel_dirs(vec![missing, first.0.clone(), second.0.clone()]);
+        let names: Vec<_> = manager.find_kernel_specs().unwrap().into_keys().collect();
+        assert_eq!(names, vec!["broken", "ir", "python3"]);
+
+        let spec = manager.get_kernel_spec("PYTHON3").unwrap();
+        assert_eq!(spec.display_name, "First");
+        assert_eq!(spec.resource_dir, Some(first.0.join("Python3")));
+
+        let all = manager.get_all_specs().unwrap();
+        assert_eq!(all.keys().collect::<Vec<_>>(), vec!["ir", "python3"]);
+
+        match manager.get_kernel_spec("julia") {
+            Err(Error::NoSuchKernel(name)) => assert_eq!(name, "julia"),
+            other => unreachable!("expected a missing kernel, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_interrupt_mode_parsing() {
+        let mode: InterruptMode = serde_json::from_str(r#""message""#).unwrap();
+        assert_eq!(mode, InterruptMode::Message);
+        let mode: InterruptMode = serde_json::from_str(r#""signal""#).unwrap();
+        assert_eq!(mode, InterruptMode::Signal);
+        assert_eq!(InterruptMode::default(), InterruptMode::Signal);
+    }
+
+    #[test]
+    fn test_kernel_spec_validation() {
+        let dir = TempDir::new();
+        write_spec(
+            &dir.0,
+            "bad",
+            json!({
+                "argv": [],
+                "language": 3,
+                "interrupt_mode": "nicely",
+                "env": {"A": "1", "B": 2},
+                "metadata": [],
+            }),
+        );
+        match KernelSpec::from_dir(dir.0.join("bad")) {
+            Err(Error::InvalidKernelSpec { path, problems }) => {
+                assert_eq!(path, dir.0.join("bad").join(KERNEL_JSON));
+                assert_eq!(
+                    problems,
+                    vec![
+                        "`argv` must be a non-empty list of strings",
+                        "`display_name` is required",
+                        "`language` must be a string",
+                        "`interrupt_mode` must be \"signal\" or \"message\", not \"nicely\"",
+                        "`env.B` must be a string",
+                        "`metadata` must be an object",
+                    ]
+                );
+            }
+            other => unreachable!("expected an invalid kernelspec, got {:?}", other),
+        }
+
+        fs::create_dir_all(dir.0.join("garbage")).unwrap();
+        fs::write(dir.0.join("garbage").join(KERNEL_JSON), "{").unwrap();
+        let err = KernelSpec::from_dir(dir.0.join("garbage")).unwrap_err();
+        assert!(err.to_string().contains("not valid JSON"), "{}", err);
+    }
+
+    #[test]
+    fn test_install_and_remove() {
+        let sources = TempDir::new();
+        let prefix = TempDir::new();
+        write_spec(
+            &sources.0,
+            "Fake",
+            json!({"argv": ["fake", "{connection_file}"], "display_name": "Fake"}),
+        );
+        fs::write(sources.0.join("Fake").join("logo-32x32.png"), b"logo").unwrap();
+        let location = InstallLocation::Prefix(prefix.0.clone());
+        let kernel_dir = location.kernel_dir().unwrap();
+        assert_eq!(
+            kernel_dir,
+            prefix.0.join("share").join("jupyter").join("kernels")
+        );
+        let manager = KernelSpecManager::with_kernel_dirs(vec![kernel_dir.clone()]);
+
+        let installed = manager
+            .install_kernel_spec(sources.0.join("Fake"), None, &location, false)
+            .unwrap();
+        assert_eq!(installed, kernel_dir.join("fake"));
+        assert_eq!(fs::read(installed.join("logo-32x32.png")).unwrap(), b"logo");
+        assert_eq!(
+            manager.get_kernel_spec("fake").unwrap().display_name,
+            "Fake"
+        );
+
+        match manager.install_kernel_spec(sources.0.join("Fake"), Some("fake"), &location, false) {
+            Err(Error::InvalidOperation(_)) => {}
+            other => unreachable!("expected an existing kernelspec, got {:?}", other),
+        }
+
+        // Replacing drops resources the new kernelspec does not have
+        write_spec(
+            &sources.0,
+            "other",
+            json!({"argv": ["other"], "display_name": "Other"}),
+        );
+        manager
+            .install_kernel_spec(sources.0.join("other"), Some("fake"), &location, true)
+            .unwrap();
+        assert_eq!(
+            manager.get_kernel_spec("fake").unwrap().display_name,
+            "Other"
+        );
+        assert!(!installed.join("logo-32x32.png").exists());
+        // Neither the staging directory nor the old kernelspec is left behind
+        assert_eq!(fs::read_dir(&kernel_dir).unwrap().count(), 1);
+
+        // A failed replacement keeps the installed kernelspec
+        assert!(move_into_place(&sources.0.join("missing"), &installed).is_err());
+        assert_eq!(
+            manager.get_kernel_spec("fake").unwrap().display_name,
+            "Other"
+        );
+        assert_eq!(fs::read_dir(&kernel_dir).unwrap().count(), 1);
+
+        write_spec(&sources.0, "invalid", json!({"argv": ["x"]}));
+        match manager.install_kernel_spec(sources.0.join("invalid"), None, &location, false) {
+            Err(Error::InvalidKernelSpec { .. }) => {}
+            other => unreachable!("expected an invalid kernelspec, got {:?}", other),
+        }
+        match manager.install_kernel_spec(sources.0.join("other"), Some("a b"), &location, false) {
+            Err(Error::InvalidOperation(_)) => {}
+            other => unreachable!("expected an invalid name, got {:?}", other),
+        }
+        assert_eq!(
+            manager
+                .find_kernel_specs()
+                .unwrap()
+                .into_keys()
+                .collect::<Vec<_>>(),
+            vec!["fake"]
+        );
+
+        assert_eq!(manager.remove_kernel_spec("FAKE").unwrap(), installed);
+        assert!(!installed.exists());
+        match manager.remove_kernel_spec("fake") {
+            Err(Error::NoSuchKernel(_)) => {}
+            other => unreachable!("expected a missing kernel, got {:?}", other),
+        }
+    }
+}
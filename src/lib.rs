@@ -55,15 +55,19 @@ let response = client.send_shell_command(command)?;
 
 extern crate chrono;
 extern crate dirs;
-extern crate failure;
 extern crate glob;
 extern crate hex;
 extern crate hmac;
+#[cfg(unix)]
+extern crate libc;
 extern crate log;
 extern crate serde;
 extern crate serde_derive;
 extern crate serde_json;
+extern crate sha1;
 extern crate sha2;
+extern crate subtle;
+extern crate thiserror;
 extern crate uuid;
 extern crate zmq;
 
@@ -78,17 +82,34 @@ extern crate generic_array;
 #[macro_use]
 mod test_helpers;
 
+#[cfg(feature = "async")]
+mod async_client;
+mod background;
 mod client;
+pub mod comm;
 pub mod commands;
 mod connection_config;
+pub mod display;
 mod errors;
+pub mod execution;
 mod header;
+pub mod heartbeat;
+pub mod iopub;
+pub mod kernelspec;
+mod manager;
 mod metadata;
-mod paths;
+pub mod paths;
 pub mod responses;
+mod router;
 mod signatures;
 mod socket;
+pub mod stdin;
 mod wire;
 
+#[cfg(feature = "async")]
+pub use crate::async_client::{AsyncClient, IoPubStream};
 pub use crate::client::Client;
-pub use crate::errors::Result;
+pub use crate::errors::{Error, Result};
+pub use crate::header::Header;
+pub use crate::manager::KernelManager;
+pub use crate::metadata::Metadata;
@@ -0,0 +1,280 @@
+/*! Starting and owning local kernels.
+
+A [`KernelManager`](struct.KernelManager.html) launches a kernel process with a fresh connection
+file, hands out clients connected to it, and stops it when dropped.
+*/
+use crate::client::Client;
+use crate::connection_config::ConnectionConfig;
+use crate::errors::{Error, Result};
+use crate::kernelspec::{InterruptMode, KernelSpec, KernelSpecManager};
+use crate::paths::jupyter_runtime_dir;
+use log::{debug, warn};
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus};
+
+/// Placeholder in a kernel's `argv` replaced by the path of its connection file.
+static CONNECTION_FILE: &str = "{connection_file}";
+/// Placeholder in a kernel's `argv` replaced by its kernelspec directory.
+static RESOURCE_DIR: &str = "{resource_dir}";
+
+/** A kernel process started by this crate.
+
+The kernel is started from its [kernelspec](kernelspec/struct.KernelSpec.html), or just the
+command line in its `argv`. A connection file with free ports and a random key is written to the
+Jupyter runtime directory, and its path is passed to the kernel in place of `{connection_file}`.
+
+```no_run
+# use jupyter_client::{Result, KernelManager};
+use jupyter_client::commands::Command;
+
+# fn main() -> Result<()> {
+let kernel = KernelManager::start_named("python3")?;
+let client = kernel.client()?;
+let response = client.send_shell_command(Command::KernelInfo)?;
+println!("{:?}", response);
+# Ok(())
+# }
+```
+
+Dropping the manager kills the kernel, if it is still running, and removes its connection file.
+*/
+#[derive(Debug)]
+pub struct KernelManager {
+    process: Child,
+    connection_file: PathBuf,
+    interrupt_mode: InterruptMode,
+}
+
+impl KernelManager {
+    /// Start a kernel from its command line, with a new connection file in the Jupyter runtime
+    /// directory.
+    pub fn start<S>(argv: &[S]) -> Result<Self>
+    where
+        S: AsRef<str>,
+    {
+        let spec = KernelSpec {
+            argv: argv.iter().map(|arg| arg.as_ref().to_string()).collect(),
+            ..KernelSpec::default()
+        };
+        KernelManager::start_spec(&spec)
+    }
+
+    /// Start the installed kernel called `name`, as found by a
+    /// [`KernelSpecManager`](kernelspec/struct.KernelSpecManager.html).
+    pub fn start_named(name: &str) -> Result<Self> {
+        let spec = KernelSpecManager::new()?.get_kernel_spec(name)?;
+        KernelManager::start_in(&spec, &name.to_lowercase(), &jupyter_runtime_dir()?)
+    }
+
+    /// Start a kernel from its kernelspec, with the environment variables it sets.
+    pub fn start_spec(spec: &KernelSpec) -> Result<Self> {
+        KernelManager::start_in(spec, "", &jupyter_runtime_dir()?)
+    }
+
+    pub(crate) fn start_in(
+        spec: &KernelSpec,
+        kernel_name: &str,
+        runtime_dir: &Path,
+    ) -> Result<Self> {
+        let (program, args) = spec
+            .argv
+            .split_first()
+            .ok_or_else(|| Error::Config("kernel command line is empty".to_string()))?;
+
+        let connection_file = runtime_dir.join(format!("kernel-{}.json", uuid::Uuid::new_v4()));
+        write_connection_file(&connection_file, &new_connection_config(kernel_name)?)?;
+        debug!("wrote connection file {}", connection_file.display());
+
+        let connection_file_str = connection_file.to_string_lossy();
+        let resource_dir = spec
+            .resource_dir
+            .as_ref()
+            .map(|dir| dir.to_string_lossy())
+            .unwrap_or_default();
+        let args: Vec<String> = args
+            .iter()
+            .map(|arg| {
+                arg.replace(CONNECTION_FILE, &connection_file_str)
+                    .replace(RESOURCE_DIR, &resource_dir)
+            })
+            .collect();
+        debug!("starting kernel: {} {:?}", program, args);
+        let process = Command::new(program).args(&args).envs(&spec.env).spawn();
+        match process {
+            Ok(process) => Ok(KernelManager {
+                process,
+                connection_file,
+                interrupt_mode: spec.interrupt_mode,
+            }),
+            Err(e) => {
+                let _ = fs::remove_file(&connection_file);
+                Err(e.into())
+            }
+        }
+    }
+
+    /// Connect a new client to the kernel. The client knows the kernel's process id and
+    /// interrupt mode, so it can [interrupt](struct.Client.html#method.interrupt) it.
+    pub fn client(&self) -> Result<Client> {
+        let mut client = Client::from_reader(File::open(&self.connection_file)?)?;
+        client.set_kernel_pid(self.process.id());
+        client.set_interrupt_mode(self.interrupt_mode);
+        Ok(client)
+    }
+
+    /// Path of the kernel's connection file.
+    pub fn connection_file(&self) -> &Path {
+        &self.connection_file
+    }
+
+    /// Process id of the kernel.
+    pub fn pid(&self) -> u32 {
+        self.process.id()
+    }
+
+    /// Whether the kernel process is still running.
+    pub fn is_alive(&mut self) -> Result<bool> {
+        Ok(self.process.try_wait()?.is_none())
+    }
+
+    /// Wait for the kernel process to exit.
+    pub fn wait(&mut self) -> Result<ExitStatus> {
+        Ok(self.process.wait()?)
+    }
+
+    /// Kill the kernel process, and wait for it to exit. Killing a kernel that has already
+    /// exited does nothing.
+    pub fn kill(&mut self) -> Result<()> {
+        if self.is_alive()? {
+            self.process.kill()?;
+        }
+        self.process.wait()?;
+        Ok(())
+    }
+}
+
+impl Drop for KernelManager {
+    fn drop(&mut self) {
+        if let Err(e) = self.kill() {
+            warn!("cannot kill kernel process {}: {}", self.process.id(), e);
+        }
+        if let Err(e) = fs::remove_file(&self.connection_file) {
+            warn!(
+                "cannot remove connection file {}: {}",
+                self.connection_file.display(),
+                e
+            );
+        }
+    }
+}
+
+/// Connection details for a new kernel on this machine, with free ports and a random key.
+fn new_connection_config(kernel_name: &str) -> Result<ConnectionConfig> {
+    // Keep every port bound until all are picked, so none is picked twice
+    let listeners = (0..5)
+        .map(|_| TcpListener::bind("127.0.0.1:0"))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    let ports = listeners
+        .iter()
+        .map(|l| Ok(u32::from(l.local_addr()?.port())))
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(ConnectionConfig {
+        shell_port: ports[0],
+        iopub_port: ports[1],
+        stdin_port: ports[2],
+        control_port: ports[3],
+        hb_port: ports[4],
+        ip: "127.0.0.1".to_string(),
+        key: uuid::Uuid::new_v4().to_string(),
+        transport: "tcp".to_string(),
+        signature_scheme: "hmac-sha256".to_string(),
+        kernel_name: kernel_name.to_string(),
+    })
+}
+
+/// Write a connection file readable only by the current user, as it holds the signing key.
+fn write_connection_file(path: &Path, config: &ConnectionConfig) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path)?;
+    file.write_all(&serde_json::to_vec_pretty(config)?)?;
+    Ok(())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::test_helpers::TempDir;
+
+    fn spec(argv: &[&str]) -> KernelSpec {
+        KernelSpec {
+            argv: argv.iter().map(|arg| arg.to_string()).collect(),
+            ..KernelSpec::default()
+        }
+    }
+
+    #[test]
+    fn test_kernel_lifetime() {
+        let dir = TempDir::new();
+        // Only stays alive if it was started as the kernelspec says
+        let mut spec = spec(&[
+            "sh",
+            "-c",
+            r#"test -f "$0" && test "$1" = /kernels/fake && test "$FAKE" = 1 && exec sleep 30"#,
+            "{connection_file}",
+            "{resource_dir}",
+        ]);
+        spec.env.insert("FAKE".to_string(), "1".to_string());
+        spec.resource_dir = Some(PathBuf::from("/kernels/fake"));
+        let mut kernel = KernelManager::start_in(&spec, "fake", &dir.0).unwrap();
+        let connection_file = kernel.connection_file().to_path_buf();
+
+        let config = ConnectionConfig::from_reader(File::open(&connection_file).unwrap()).unwrap();
+        let mut ports = vec![
+            config.shell_port,
+            config.iopub_port,
+            config.stdin_port,
+            config.control_port,
+            config.hb_port,
+        ];
+        ports.sort();
+        ports.dedup();
+        assert_eq!(ports.len(), 5);
+        assert!(!config.key.is_empty());
+        assert_eq!(config.kernel_name, "fake");
+
+        kernel.client().unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        assert!(kernel.is_alive().unwrap());
+        kernel.kill().unwrap();
+        assert!(!kernel.is_alive().unwrap());
+
+        drop(kernel);
+        assert!(!connection_file.exists());
+    }
+
+    #[test]
+    fn test_kernel_exit_status() {
+        let dir = TempDir::new();
+        let mut kernel =
+            KernelManager::start_in(&spec(&["sh", "-c", "exit 3"]), "", &dir.0).unwrap();
+        assert_eq!(kernel.wait().unwrap().code(), Some(3));
+
+        match KernelManager::start_in(&spec(&[]), "", &dir.0) {
+            Err(Error::Config(_)) => {}
+            other => unreachable!("expected a config error, got {:?}", other),
+        }
+    }
+}
@@ -1,4 +1,83 @@
+use chrono::{DateTime, FixedOffset};
 use serde_derive::Deserialize;
+use serde_json::{Map, Value};
 
-#[derive(Deserialize, Debug)]
-pub struct Metadata {}
+/** Metadata sent with a message.
+
+The full JSON object is kept, as kernels are free to add their own keys. Accessors are provided
+for the keys that IPython sends with `execute_reply` messages.
+*/
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(transparent)]
+pub struct Metadata(Map<String, Value>);
+
+impl Metadata {
+    /// Raw value for a key.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.0.get(key)
+    }
+
+    /// The full metadata object.
+    pub fn as_map(&self) -> &Map<String, Value> {
+        &self.0
+    }
+
+    /// Whether the dependencies of the request were met.
+    pub fn dependencies_met(&self) -> Option<bool> {
+        self.get("dependencies_met").and_then(Value::as_bool)
+    }
+
+    /// Id of the engine that handled the request.
+    pub fn engine(&self) -> Option<&str> {
+        self.get("engine").and_then(Value::as_str)
+    }
+
+    /// Time at which the kernel started handling the request.
+    pub fn started(&self) -> Option<DateTime<FixedOffset>> {
+        self.get("started")
+            .and_then(Value::as_str)
+            .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+    }
+}
+
+impl From<Map<String, Value>> for Metadata {
+    fn from(map: Map<String, Value>) -> Self {
+        Metadata(map)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_execute_reply_metadata() {
+        let metadata: Metadata = serde_json::from_value(json!({
+            "dependencies_met": true,
+            "engine": "dcb3a0ac-6c4e-4b3b-9b84-4a8bfa7f0b7b",
+            "started": "2019-01-02T03:04:05.678901Z",
+            "status": "ok",
+        }))
+        .unwrap();
+
+        assert_eq!(metadata.dependencies_met(), Some(true));
+        assert_eq!(
+            metadata.engine(),
+            Some("dcb3a0ac-6c4e-4b3b-9b84-4a8bfa7f0b7b")
+        );
+        assert_eq!(
+            metadata.started().unwrap().to_rfc3339(),
+            "2019-01-02T03:04:05.678901+00:00"
+        );
+        assert_eq!(metadata.get("status"), Some(&json!("ok")));
+    }
+
+    #[test]
+    fn test_empty_metadata() {
+        let metadata: Metadata = serde_json::from_str("{}").unwrap();
+        assert_eq!(metadata, Metadata::default());
+        assert_eq!(metadata.dependencies_met(), None);
+        assert_eq!(metadata.started(), None);
+    }
+}
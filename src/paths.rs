@@ -1,58 +1,389 @@
-use dirs::home_dir;
+/*! Jupyter directories, resolved as in Python's `jupyter_core`.
+
+Each directory can be overridden with an environment variable, as described in the
+[Jupyter documentation][jupyter-paths]. The search paths, [`jupyter_path`](fn.jupyter_path.html)
+and [`jupyter_config_path`](fn.jupyter_config_path.html), list directories highest priority first:
+
+1. directories listed in `JUPYTER_PATH` or `JUPYTER_CONFIG_PATH`,
+2. the user directory,
+3. the directory of the active Python environment, from `VIRTUAL_ENV` or `CONDA_PREFIX`,
+4. the system directories.
+
+The environment directory comes before the user directory if `JUPYTER_PREFER_ENV_PATH` is set
+to a true value, which is the default inside a virtualenv or a conda environment other than
+`base`. Setting `JUPYTER_PLATFORM_DIRS` uses the platform's standard user directories instead of
+the historical `~/.jupyter` and friends.
+
+[jupyter-paths]: https://docs.jupyter.org/en/latest/use/jupyter-directories.html
+*/
+use crate::errors::{Error, Result};
+use std::collections::HashMap;
 use std::env;
+use std::ffi::{OsStr, OsString};
 use std::path::PathBuf;
 
-pub(crate) fn jupyter_runtime_dir() -> PathBuf {
-    if let Ok(p) = env::var("JUPYTER_RUNTIME_DIR") {
-        PathBuf::from(p)
-    } else {
-        os_jupyter_runtime_dir()
-    }
+/// Environment variables that affect the Jupyter directories.
+static VARIABLES: &[&str] = &[
+    "APPDATA",
+    "CONDA_DEFAULT_ENV",
+    "CONDA_PREFIX",
+    "JUPYTER_CONFIG_DIR",
+    "JUPYTER_CONFIG_PATH",
+    "JUPYTER_DATA_DIR",
+    "JUPYTER_PATH",
+    "JUPYTER_PLATFORM_DIRS",
+    "JUPYTER_PREFER_ENV_PATH",
+    "JUPYTER_RUNTIME_DIR",
+    "PROGRAMDATA",
+    "VIRTUAL_ENV",
+    "XDG_DATA_HOME",
+];
+
+/// Directory for user configuration files, `~/.jupyter` by default.
+pub fn jupyter_config_dir() -> Result<PathBuf> {
+    Environment::current().config_dir()
 }
 
-pub(crate) fn jupyter_data_dir() -> PathBuf {
-    if let Ok(p) = env::var("JUPYTER_DATA_DIR") {
-        PathBuf::from(p)
-    } else {
-        os_jupyter_data_dir()
-    }
+/// Directory for user data files such as kernelspecs, e.g. `~/.local/share/jupyter` on Linux.
+pub fn jupyter_data_dir() -> Result<PathBuf> {
+    Environment::current().data_dir()
 }
 
-#[cfg(any(target_os = "macos", target_os = "windows"))]
-fn os_jupyter_runtime_dir() -> PathBuf {
-    jupyter_data_dir().join("runtime")
+/// Directory for runtime files such as connection files, `runtime` in the data directory by
+/// default.
+pub fn jupyter_runtime_dir() -> Result<PathBuf> {
+    Environment::current().runtime_dir()
 }
 
-#[cfg(not(any(target_os = "macos", target_os = "windows")))]
-fn os_jupyter_runtime_dir() -> PathBuf {
-    if let Ok(p) = env::var("XDG_RUNTIME_DIR") {
-        PathBuf::from(p).join("jupyter")
-    } else {
-        jupyter_data_dir().join("runtime")
-    }
+/// Directories searched for data files such as kernelspecs, highest priority first.
+pub fn jupyter_path() -> Result<Vec<PathBuf>> {
+    Environment::current().path()
 }
 
-#[cfg(target_os = "macos")]
-fn os_jupyter_data_dir() -> PathBuf {
-    let home = home_dir().unwrap();
-    home.join("Library").join("Jupyter")
+/// Directories searched for configuration files, highest priority first.
+pub fn jupyter_config_path() -> Result<Vec<PathBuf>> {
+    Environment::current().config_path()
 }
 
-#[cfg(target_os = "linux")]
-fn os_jupyter_data_dir() -> PathBuf {
-    if let Ok(p) = env::var("XDG_DATA_HOME") {
-        PathBuf::from(p).join("jupyter")
-    } else {
-        let home = home_dir().unwrap();
-        home.join(".local").join("share")
+/// Snapshot of the environment the directories are resolved from.
+struct Environment {
+    vars: HashMap<&'static str, OsString>,
+    home: Option<PathBuf>,
+}
+
+impl Environment {
+    fn current() -> Self {
+        Environment {
+            vars: VARIABLES
+                .iter()
+                .filter_map(|&name| env::var_os(name).map(|value| (name, value)))
+                .collect(),
+            home: dirs::home_dir(),
+        }
+    }
+
+    /// A variable, if it is set and not empty.
+    fn var(&self, name: &str) -> Option<&OsStr> {
+        self.vars
+            .get(name)
+            .map(OsString::as_os_str)
+            .filter(|value| !value.is_empty())
+    }
+
+    /// A boolean variable, which is true unless set to a value like `0` or `false`.
+    fn flag(&self, name: &str) -> Option<bool> {
+        let value = self.vars.get(name)?.to_string_lossy().to_lowercase();
+        Some(!["no", "n", "false", "off", "0", "0.0"].contains(&value.as_str()))
+    }
+
+    fn home(&self) -> Result<PathBuf> {
+        self.home
+            .clone()
+            .ok_or_else(|| Error::Config("cannot find the home directory".to_string()))
+    }
+
+    fn use_platform_dirs(&self) -> bool {
+        self.flag("JUPYTER_PLATFORM_DIRS").unwrap_or(false)
+    }
+
+    fn config_dir(&self) -> Result<PathBuf> {
+        if let Some(dir) = self.var("JUPYTER_CONFIG_DIR") {
+            return Ok(PathBuf::from(dir));
+        }
+        if self.use_platform_dirs() {
+            return platform_dir(dirs::config_local_dir());
+        }
+        Ok(self.home()?.join(".jupyter"))
+    }
+
+    fn data_dir(&self) -> Result<PathBuf> {
+        if let Some(dir) = self.var("JUPYTER_DATA_DIR") {
+            return Ok(PathBuf::from(dir));
+        }
+        if self.use_platform_dirs() {
+            return platform_dir(dirs::data_local_dir());
+        }
+        if cfg!(target_os = "macos") {
+            Ok(self.home()?.join("Library").join("Jupyter"))
+        } else if cfg!(target_os = "windows") {
+            match self.var("APPDATA") {
+                Some(app_data) => Ok(PathBuf::from(app_data).join("jupyter")),
+                None => Ok(self.config_dir()?.join("data")),
+            }
+        } else {
+            let data_home = match self.var("XDG_DATA_HOME") {
+                Some(dir) => PathBuf::from(dir),
+                None => self.home()?.join(".local").join("share"),
+            };
+            Ok(data_home.join("jupyter"))
+        }
+    }
+
+    fn runtime_dir(&self) -> Result<PathBuf> {
+        match self.var("JUPYTER_RUNTIME_DIR") {
+            Some(dir) => Ok(PathBuf::from(dir)),
+            None => Ok(self.data_dir()?.join("runtime")),
+        }
+    }
+
+    fn path(&self) -> Result<Vec<PathBuf>> {
+        let env = self.env_prefix().map(|p| p.join("share").join("jupyter"));
+        let system = self.system_path();
+        self.search_path("JUPYTER_PATH", self.data_dir()?, env, system)
+    }
+
+    fn config_path(&self) -> Result<Vec<PathBuf>> {
+        let env = self.env_prefix().map(|p| p.join("etc").join("jupyter"));
+        let system = self.system_config_path();
+        self.search_path("JUPYTER_CONFIG_PATH", self.config_dir()?, env, system)
+    }
+
+    /// Directories listed in `variable`, then the user, environment and system directories.
+    fn search_path(
+        &self,
+        variable: &str,
+        user: PathBuf,
+        env: Option<PathBuf>,
+        system: Vec<PathBuf>,
+    ) -> Result<Vec<PathBuf>> {
+        let mut paths: Vec<PathBuf> = self
+            .var(variable)
+            .map(|p| {
+                env::split_paths(p)
+                    .filter(|p| !p.as_os_str().is_empty())
+                    .collect()
+            })
+            .unwrap_or_default();
+        let env = env.filter(|p| !system.contains(p));
+        if self.prefer_env_path() {
+            paths.extend(env);
+            paths.push(user);
+        } else {
+            paths.push(user);
+            paths.extend(env);
+        }
+        paths.extend(system);
+        Ok(paths)
+    }
+
+    /// Prefix of the active Python environment, if any.
+    fn env_prefix(&self) -> Option<PathBuf> {
+        self.var("VIRTUAL_ENV")
+            .or_else(|| self.var("CONDA_PREFIX"))
+            .map(PathBuf::from)
+    }
+
+    fn prefer_env_path(&self) -> bool {
+        if let Some(prefer) = self.flag("JUPYTER_PREFER_ENV_PATH") {
+            return prefer;
+        }
+        let in_conda_env = self.var("CONDA_PREFIX").is_some()
+            && self
+                .var("CONDA_DEFAULT_ENV")
+                .is_some_and(|name| name != "base");
+        self.var("VIRTUAL_ENV").is_some() || in_conda_env
+    }
+
+    fn system_path(&self) -> Vec<PathBuf> {
+        if cfg!(target_os = "windows") {
+            match self.var("PROGRAMDATA") {
+                Some(program_data) => vec![PathBuf::from(program_data).join("jupyter")],
+                None => self
+                    .env_prefix()
+                    .map(|p| p.join("share").join("jupyter"))
+                    .into_iter()
+                    .collect(),
+            }
+        } else {
+            vec![
+                PathBuf::from("/usr/local/share/jupyter"),
+                PathBuf::from("/usr/share/jupyter"),
+            ]
+        }
     }
+
+    fn system_config_path(&self) -> Vec<PathBuf> {
+        if cfg!(target_os = "windows") {
+            self.var("PROGRAMDATA")
+                .map(|program_data| PathBuf::from(program_data).join("jupyter"))
+                .into_iter()
+                .collect()
+        } else {
+            vec![
+                PathBuf::from("/usr/local/etc/jupyter"),
+                PathBuf::from("/etc/jupyter"),
+            ]
+        }
+    }
+}
+
+/// The Jupyter subdirectory of a platform directory.
+fn platform_dir(dir: Option<PathBuf>) -> Result<PathBuf> {
+    dir.map(|dir| dir.join("jupyter"))
+        .ok_or_else(|| Error::Config("cannot find the platform's user directories".to_string()))
 }
 
-#[cfg(target_os = "windows")]
-fn os_jupyter_data_dir() -> PathBuf {
-    if let Ok(app_data) = env::var("APPDATA") {
-        PathBuf::from(app_data).join("jupyter")
-    } else {
-        unimplemented!()
+/// System-wide data directories, where kernelspecs are installed for all users.
+pub(crate) fn system_jupyter_path() -> Vec<PathBuf> {
+    Environment::current().system_path()
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    fn environment(vars: &[(&'static str, &str)]) -> Environment {
+        Environment {
+            vars: vars
+                .iter()
+                .map(|&(name, value)| (name, OsString::from(value)))
+                .collect(),
+            home: Some(PathBuf::from("/home/user")),
+        }
+    }
+
+    fn paths(paths: &[&str]) -> Vec<PathBuf> {
+        paths.iter().map(PathBuf::from).collect()
+    }
+
+    #[test]
+    fn test_default_dirs() {
+        let env = environment(&[]);
+        assert_eq!(
+            env.config_dir().unwrap(),
+            PathBuf::from("/home/user/.jupyter")
+        );
+        assert_eq!(
+            env.data_dir().unwrap(),
+            PathBuf::from("/home/user/.local/share/jupyter")
+        );
+        assert_eq!(
+            env.runtime_dir().unwrap(),
+            PathBuf::from("/home/user/.local/share/jupyter/runtime")
+        );
+        assert_eq!(
+            env.path().unwrap(),
+            paths(&[
+                "/home/user/.local/share/jupyter",
+                "/usr/local/share/jupyter",
+                "/usr/share/jupyter",
+            ])
+        );
+        assert_eq!(
+            env.config_path().unwrap(),
+            paths(&[
+                "/home/user/.jupyter",
+                "/usr/local/etc/jupyter",
+                "/etc/jupyter"
+            ])
+        );
+
+        let env = Environment {
+            home: None,
+            ..environment(&[])
+        };
+        match env.data_dir() {
+            Err(Error::Config(_)) => {}
+            other => unreachable!("expected a config error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_overridden_dirs() {
+        let env = environment(&[
+            ("JUPYTER_CONFIG_DIR", "/config"),
+            ("JUPYTER_DATA_DIR", "/data"),
+            ("JUPYTER_RUNTIME_DIR", ""),
+            ("JUPYTER_PATH", "/first::/second"),
+            ("JUPYTER_CONFIG_PATH", "/config-first"),
+        ]);
+        assert_eq!(env.config_dir().unwrap(), PathBuf::from("/config"));
+        assert_eq!(env.runtime_dir().unwrap(), PathBuf::from("/data/runtime"));
+        assert_eq!(
+            env.path().unwrap(),
+            paths(&[
+                "/first",
+                "/second",
+                "/data",
+                "/usr/local/share/jupyter",
+                "/usr/share/jupyter",
+            ])
+        );
+        assert_eq!(
+            env.config_path().unwrap()[..2],
+            paths(&["/config-first", "/config"])[..]
+        );
+
+        let env = environment(&[("XDG_DATA_HOME", "/xdg")]);
+        assert_eq!(env.data_dir().unwrap(), PathBuf::from("/xdg/jupyter"));
+    }
+
+    #[test]
+    fn test_environment_precedence() {
+        let env = environment(&[("VIRTUAL_ENV", "/venv")]);
+        assert_eq!(
+            env.path().unwrap()[..2],
+            paths(&["/venv/share/jupyter", "/home/user/.local/share/jupyter"])[..]
+        );
+        assert_eq!(
+            env.config_path().unwrap()[..2],
+            paths(&["/venv/etc/jupyter", "/home/user/.jupyter"])[..]
+        );
+
+        let env = environment(&[("VIRTUAL_ENV", "/venv"), ("JUPYTER_PREFER_ENV_PATH", "no")]);
+        assert_eq!(
+            env.path().unwrap()[..2],
+            paths(&["/home/user/.local/share/jupyter", "/venv/share/jupyter"])[..]
+        );
+
+        let env = environment(&[("CONDA_PREFIX", "/conda"), ("CONDA_DEFAULT_ENV", "base")]);
+        assert_eq!(
+            env.path().unwrap()[..2],
+            paths(&["/home/user/.local/share/jupyter", "/conda/share/jupyter"])[..]
+        );
+
+        // The environment directory is not repeated when it is also a system directory
+        let env = environment(&[("VIRTUAL_ENV", "/usr/local")]);
+        assert_eq!(
+            env.path().unwrap(),
+            paths(&[
+                "/home/user/.local/share/jupyter",
+                "/usr/local/share/jupyter",
+                "/usr/share/jupyter",
+            ])
+        );
+    }
+
+    #[test]
+    fn test_platform_dirs() {
+        let env = environment(&[("JUPYTER_PLATFORM_DIRS", "1")]);
+        assert_eq!(
+            env.data_dir().unwrap(),
+            dirs::data_local_dir().unwrap().join("jupyter")
+        );
+        assert_eq!(
+            env.config_dir().unwrap(),
+            dirs::config_local_dir().unwrap().join("jupyter")
+        );
     }
 }
@@ -8,7 +8,7 @@ use serde_json::Value;
 use std::collections::HashMap;
 
 /// Link pointing to some help text.
-#[derive(Deserialize, Debug, PartialEq, Eq)]
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct HelpLink {
     /// The text to display.
     pub text: String,
@@ -18,199 +18,483 @@ pub struct HelpLink {
 
 /** Overall response type
 
-There are two responses available:
+There are three responses available:
 
-- responses that come from sending a shell message, and
-- responses that come from the IOPub socket.
+- responses that come from sending a shell message,
+- responses that come from the IOPub socket, and
+- requests from the kernel that arrive on the stdin socket.
 
-These two responses are then wrapped into a single `Response` type so that functions can return any
-response.
+These responses are then wrapped into a single `Response` type so that functions can return any
+response. Messages of a type this crate does not know about are returned as
+[`Response::Unknown`](#variant.Unknown) with their raw contents, so they can still be logged or
+handled by the caller.
 */
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Response {
     /// Response from sending a shell message.
     Shell(ShellResponse),
     /// Response from the IOPub socket, sent from the kernel.
     IoPub(IoPubResponse),
+    /// Request from the kernel on the stdin socket.
+    Stdin(StdinResponse),
+    /// Message with a type that this crate does not know how to parse.
+    Unknown {
+        /// Header from the kernel.
+        header: Header,
+        /// Header of the request that caused this message, if any.
+        parent_header: Option<Header>,
+        /// Raw metadata.
+        metadata: Value,
+        /// Raw message content.
+        content: Value,
+        /// Binary buffers sent after the message content.
+        buffers: Vec<Vec<u8>>,
+        /// ZMQ routing identities that preceded the message.
+        identities: Vec<Vec<u8>>,
+    },
 }
 
 /// Responses from sending shell messages.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum ShellResponse {
     /// Response from asking for information about the running kernel.
     KernelInfo {
         /// Header from the kernel.
         header: Header,
-        /// Header sent to the kernel.
-        parent_header: Header,
+        /// Header sent to the kernel, or `None` if the message was not caused by a request.
+        parent_header: Option<Header>,
         /// Metadata about the response.
         metadata: Metadata,
         /// Main response content.
         content: KernelInfoContent,
+        /// Binary buffers sent with the message.
+        buffers: Vec<Vec<u8>>,
+        /// ZMQ routing identities that preceded the message.
+        identities: Vec<Vec<u8>>,
     },
     /// Response from sending an execute request.
     Execute {
         /// Header from the kernel.
         header: Header,
-        /// Header sent to the kernel.
-        parent_header: Header,
+        /// Header sent to the kernel, or `None` if the message was not caused by a request.
+        parent_header: Option<Header>,
         /// Metadata about the response.
         metadata: Metadata,
         /// Main response content.
         content: ExecuteReplyContent,
+        /// Binary buffers sent with the message.
+        buffers: Vec<Vec<u8>>,
+        /// ZMQ routing identities that preceded the message.
+        identities: Vec<Vec<u8>>,
     },
     /// Response from inspecting a code block.
     Inspect {
         /// Header from the kernel.
         header: Header,
-        /// Header sent to the kernel.
-        parent_header: Header,
+        /// Header sent to the kernel, or `None` if the message was not caused by a request.
+        parent_header: Option<Header>,
         /// Metadata about the response.
         metadata: Metadata,
         /// Main response content.
         content: InspectContent,
+        /// Binary buffers sent with the message.
+        buffers: Vec<Vec<u8>>,
+        /// ZMQ routing identities that preceded the message.
+        identities: Vec<Vec<u8>>,
     },
     /// Resposne from asking for code completion.
     Complete {
         /// Header from the kernel.
         header: Header,
-        /// Header sent to the kernel.
-        parent_header: Header,
+        /// Header sent to the kernel, or `None` if the message was not caused by a request.
+        parent_header: Option<Header>,
         /// Metadata about the response.
         metadata: Metadata,
         /// Main response content.
         content: CompleteContent,
+        /// Binary buffers sent with the message.
+        buffers: Vec<Vec<u8>>,
+        /// ZMQ routing identities that preceded the message.
+        identities: Vec<Vec<u8>>,
     },
     /// Response from fetching kernel command history.
     History {
         /// Header from the kernel.
         header: Header,
-        /// Header sent to the kernel.
-        parent_header: Header,
+        /// Header sent to the kernel, or `None` if the message was not caused by a request.
+        parent_header: Option<Header>,
         /// Metadata about the response.
         metadata: Metadata,
         /// Main response content.
         content: HistoryContent,
+        /// Binary buffers sent with the message.
+        buffers: Vec<Vec<u8>>,
+        /// ZMQ routing identities that preceded the message.
+        identities: Vec<Vec<u8>>,
     },
     /// Response from asking the kernel if the code is complete.
     IsComplete {
         /// Header from the kernel.
         header: Header,
-        /// Header sent to the kernel.
-        parent_header: Header,
+        /// Header sent to the kernel, or `None` if the message was not caused by a request.
+        parent_header: Option<Header>,
         /// Metadata about the response.
         metadata: Metadata,
         /// Main response content.
         content: IsCompleteStatus,
+        /// Binary buffers sent with the message.
+        buffers: Vec<Vec<u8>>,
+        /// ZMQ routing identities that preceded the message.
+        identities: Vec<Vec<u8>>,
     },
     /// Response from asking to shut down the kernel.
     Shutdown {
         /// Header from the kernel.
         header: Header,
-        /// Header sent to the kernel.
-        parent_header: Header,
+        /// Header sent to the kernel, or `None` if the message was not caused by a request.
+        parent_header: Option<Header>,
         /// Metadata about the response.
         metadata: Metadata,
         /// Main response content.
         content: ShutdownContent,
+        /// Binary buffers sent with the message.
+        buffers: Vec<Vec<u8>>,
+        /// ZMQ routing identities that preceded the message.
+        identities: Vec<Vec<u8>>,
     },
     /// Response from asking information about comms.
     CommInfo {
         /// Header from the kernel.
         header: Header,
-        /// Header sent to the kernel.
-        parent_header: Header,
+        /// Header sent to the kernel, or `None` if the message was not caused by a request.
+        parent_header: Option<Header>,
         /// Metadata about the response.
         metadata: Metadata,
         /// Main response content.
         content: CommInfoContent,
+        /// Binary buffers sent with the message.
+        buffers: Vec<Vec<u8>>,
+        /// ZMQ routing identities that preceded the message.
+        identities: Vec<Vec<u8>>,
+    },
+    /// Response from asking to interrupt the kernel.
+    Interrupt {
+        /// Header from the kernel.
+        header: Header,
+        /// Header sent to the kernel, or `None` if the message was not caused by a request.
+        parent_header: Option<Header>,
+        /// Metadata about the response.
+        metadata: Metadata,
+        /// Main response content.
+        content: InterruptContent,
+        /// Binary buffers sent with the message.
+        buffers: Vec<Vec<u8>>,
+        /// ZMQ routing identities that preceded the message.
+        identities: Vec<Vec<u8>>,
     },
 }
 
 /// Responses from the IOPub channel.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum IoPubResponse {
     /// Response from the kernel showing the current kernel status.
     Status {
         /// Header from the kernel.
         header: Header,
-        /// Header sent to the kernel.
-        parent_header: Header,
+        /// Header sent to the kernel, or `None` if the message was not caused by a request.
+        parent_header: Option<Header>,
         /// Metadata about the response.
         metadata: Metadata,
         /// Main response content.
         content: StatusContent,
+        /// Binary buffers sent with the message.
+        buffers: Vec<Vec<u8>>,
+        /// ZMQ routing identities that preceded the message.
+        identities: Vec<Vec<u8>>,
     },
     /// Response when any code is run so all clients are aware of it.
     ExecuteInput {
         /// Header from the kernel.
         header: Header,
-        /// Header sent to the kernel.
-        parent_header: Header,
+        /// Header sent to the kernel, or `None` if the message was not caused by a request.
+        parent_header: Option<Header>,
         /// Metadata about the response.
         metadata: Metadata,
         /// Main response content.
         content: ExecuteInputContent,
+        /// Binary buffers sent with the message.
+        buffers: Vec<Vec<u8>>,
+        /// ZMQ routing identities that preceded the message.
+        identities: Vec<Vec<u8>>,
     },
     /// Response when something is written to stdout/stderr.
     Stream {
         /// Header from the kernel.
         header: Header,
-        /// Header sent to the kernel.
-        parent_header: Header,
+        /// Header sent to the kernel, or `None` if the message was not caused by a request.
+        parent_header: Option<Header>,
         /// Metadata about the response.
         metadata: Metadata,
         /// Main response content.
         content: StreamContent,
+        /// Binary buffers sent with the message.
+        buffers: Vec<Vec<u8>>,
+        /// ZMQ routing identities that preceded the message.
+        identities: Vec<Vec<u8>>,
     },
     /// Response when a response has mutliple formats.
     ExecuteResult {
         /// Header from the kernel.
         header: Header,
-        /// Header sent to the kernel.
-        parent_header: Header,
+        /// Header sent to the kernel, or `None` if the message was not caused by a request.
+        parent_header: Option<Header>,
         /// Metadata about the response.
         metadata: Metadata,
         /// Main response content.
         content: ExecuteResultContent,
+        /// Binary buffers sent with the message.
+        buffers: Vec<Vec<u8>>,
+        /// ZMQ routing identities that preceded the message.
+        identities: Vec<Vec<u8>>,
     },
     /// Response when an error occurs.
     Error {
         /// Header from the kernel.
         header: Header,
-        /// Header sent to the kernel.
-        parent_header: Header,
+        /// Header sent to the kernel, or `None` if the message was not caused by a request.
+        parent_header: Option<Header>,
         /// Metadata about the response.
         metadata: Metadata,
         /// Main response content.
         content: ErrorContent,
+        /// Binary buffers sent with the message.
+        buffers: Vec<Vec<u8>>,
+        /// ZMQ routing identities that preceded the message.
+        identities: Vec<Vec<u8>>,
     },
     /// Response when the kernel askes the client to clear it's output.
     ClearOutput {
         /// Header from the kernel.
         header: Header,
-        /// Header sent to the kernel.
-        parent_header: Header,
+        /// Header sent to the kernel, or `None` if the message was not caused by a request.
+        parent_header: Option<Header>,
         /// Metadata about the response.
         metadata: Metadata,
         /// Main response content.
         content: ClearOutputContent,
+        /// Binary buffers sent with the message.
+        buffers: Vec<Vec<u8>>,
+        /// ZMQ routing identities that preceded the message.
+        identities: Vec<Vec<u8>>,
     },
-    /// Response when the kernel askes the client to clear it's output. (@l-yc)
+    /// Response when the kernel asks the client to display some data. (@l-yc)
     DisplayData {
         /// Header from the kernel.
         header: Header,
-        /// Header sent to the kernel.
-        parent_header: Header,
+        /// Header sent to the kernel, or `None` if the message was not caused by a request.
+        parent_header: Option<Header>,
+        /// Metadata about the response.
+        metadata: Metadata,
+        /// Main response content.
+        content: DisplayDataContent,
+        /// Binary buffers sent with the message.
+        buffers: Vec<Vec<u8>>,
+        /// ZMQ routing identities that preceded the message.
+        identities: Vec<Vec<u8>>,
+    },
+    /// Response when the kernel updates an existing display, identified by its `display_id`.
+    UpdateDisplayData {
+        /// Header from the kernel.
+        header: Header,
+        /// Header sent to the kernel, or `None` if the message was not caused by a request.
+        parent_header: Option<Header>,
         /// Metadata about the response.
         metadata: Metadata,
         /// Main response content.
         content: DisplayDataContent,
+        /// Binary buffers sent with the message.
+        buffers: Vec<Vec<u8>>,
+        /// ZMQ routing identities that preceded the message.
+        identities: Vec<Vec<u8>>,
+    },
+    /// The kernel has opened a comm.
+    CommOpen {
+        /// Header from the kernel.
+        header: Header,
+        /// Header sent to the kernel, or `None` if the message was not caused by a request.
+        parent_header: Option<Header>,
+        /// Metadata about the response.
+        metadata: Metadata,
+        /// Main response content.
+        content: CommOpenContent,
+        /// Binary buffers sent with the message.
+        buffers: Vec<Vec<u8>>,
+        /// ZMQ routing identities that preceded the message.
+        identities: Vec<Vec<u8>>,
+    },
+    /// Message sent on an open comm.
+    CommMsg {
+        /// Header from the kernel.
+        header: Header,
+        /// Header sent to the kernel, or `None` if the message was not caused by a request.
+        parent_header: Option<Header>,
+        /// Metadata about the response.
+        metadata: Metadata,
+        /// Main response content.
+        content: CommMsgContent,
+        /// Binary buffers sent with the message.
+        buffers: Vec<Vec<u8>>,
+        /// ZMQ routing identities that preceded the message.
+        identities: Vec<Vec<u8>>,
+    },
+    /// The kernel has closed a comm.
+    CommClose {
+        /// Header from the kernel.
+        header: Header,
+        /// Header sent to the kernel, or `None` if the message was not caused by a request.
+        parent_header: Option<Header>,
+        /// Metadata about the response.
+        metadata: Metadata,
+        /// Main response content.
+        content: CommCloseContent,
+        /// Binary buffers sent with the message.
+        buffers: Vec<Vec<u8>>,
+        /// ZMQ routing identities that preceded the message.
+        identities: Vec<Vec<u8>>,
+    },
+}
+
+/// Messages from the kernel on the stdin channel.
+#[derive(Debug, Clone)]
+pub enum StdinResponse {
+    /// The kernel is asking the user for input, e.g. from Python's `input()`.
+    InputRequest {
+        /// Header from the kernel.
+        header: Header,
+        /// Header of the request that triggered the input request.
+        parent_header: Option<Header>,
+        /// Metadata about the request.
+        metadata: Metadata,
+        /// Main request content.
+        content: InputRequestContent,
+        /// Binary buffers sent with the message.
+        buffers: Vec<Vec<u8>>,
+        /// ZMQ routing identities that preceded the message.
+        identities: Vec<Vec<u8>>,
     },
 }
 
+impl Response {
+    /// Header of the message.
+    pub fn header(&self) -> &Header {
+        match self {
+            Response::Shell(r) => r.header(),
+            Response::IoPub(r) => r.header(),
+            Response::Stdin(r) => r.header(),
+            Response::Unknown { header, .. } => header,
+        }
+    }
+
+    /** Header of the request that caused this message.
+
+    This is `None` for messages the kernel sent on its own.
+    */
+    pub fn parent_header(&self) -> Option<&Header> {
+        match self {
+            Response::Shell(r) => r.parent_header(),
+            Response::IoPub(r) => r.parent_header(),
+            Response::Stdin(r) => r.parent_header(),
+            Response::Unknown { parent_header, .. } => parent_header.as_ref(),
+        }
+    }
+}
+
+impl ShellResponse {
+    /// Header of the message.
+    pub fn header(&self) -> &Header {
+        match self {
+            ShellResponse::KernelInfo { header, .. }
+            | ShellResponse::Execute { header, .. }
+            | ShellResponse::Inspect { header, .. }
+            | ShellResponse::Complete { header, .. }
+            | ShellResponse::History { header, .. }
+            | ShellResponse::IsComplete { header, .. }
+            | ShellResponse::Shutdown { header, .. }
+            | ShellResponse::CommInfo { header, .. }
+            | ShellResponse::Interrupt { header, .. } => header,
+        }
+    }
+
+    /// Header of the request that caused this message, if any.
+    pub fn parent_header(&self) -> Option<&Header> {
+        match self {
+            ShellResponse::KernelInfo { parent_header, .. }
+            | ShellResponse::Execute { parent_header, .. }
+            | ShellResponse::Inspect { parent_header, .. }
+            | ShellResponse::Complete { parent_header, .. }
+            | ShellResponse::History { parent_header, .. }
+            | ShellResponse::IsComplete { parent_header, .. }
+            | ShellResponse::Shutdown { parent_header, .. }
+            | ShellResponse::CommInfo { parent_header, .. }
+            | ShellResponse::Interrupt { parent_header, .. } => parent_header.as_ref(),
+        }
+    }
+}
+
+impl IoPubResponse {
+    /// Header of the message.
+    pub fn header(&self) -> &Header {
+        match self {
+            IoPubResponse::Status { header, .. }
+            | IoPubResponse::ExecuteInput { header, .. }
+            | IoPubResponse::Stream { header, .. }
+            | IoPubResponse::ExecuteResult { header, .. }
+            | IoPubResponse::Error { header, .. }
+            | IoPubResponse::ClearOutput { header, .. }
+            | IoPubResponse::DisplayData { header, .. }
+            | IoPubResponse::UpdateDisplayData { header, .. }
+            | IoPubResponse::CommOpen { header, .. }
+            | IoPubResponse::CommMsg { header, .. }
+            | IoPubResponse::CommClose { header, .. } => header,
+        }
+    }
+
+    /// Header of the request that caused this message, if any.
+    pub fn parent_header(&self) -> Option<&Header> {
+        match self {
+            IoPubResponse::Status { parent_header, .. }
+            | IoPubResponse::ExecuteInput { parent_header, .. }
+            | IoPubResponse::Stream { parent_header, .. }
+            | IoPubResponse::ExecuteResult { parent_header, .. }
+            | IoPubResponse::Error { parent_header, .. }
+            | IoPubResponse::ClearOutput { parent_header, .. }
+            | IoPubResponse::DisplayData { parent_header, .. }
+            | IoPubResponse::UpdateDisplayData { parent_header, .. }
+            | IoPubResponse::CommOpen { parent_header, .. }
+            | IoPubResponse::CommMsg { parent_header, .. }
+            | IoPubResponse::CommClose { parent_header, .. } => parent_header.as_ref(),
+        }
+    }
+}
+
+impl StdinResponse {
+    /// Header of the message.
+    pub fn header(&self) -> &Header {
+        match self {
+            StdinResponse::InputRequest { header, .. } => header,
+        }
+    }
+
+    /// Header of the request that caused this message, if any.
+    pub fn parent_header(&self) -> Option<&Header> {
+        match self {
+            StdinResponse::InputRequest { parent_header, .. } => parent_header.as_ref(),
+        }
+    }
+}
+
 /// Content for a KernelInfo response.
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct KernelInfoContent {
     /// Status of the request.
     pub status: Status,
@@ -229,7 +513,7 @@ pub struct KernelInfoContent {
 }
 
 /// Information about the language of code for the kernel.
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct LanguageInfo {
     /// Name of the programming language the kernel implements.
     pub name: String,
@@ -249,7 +533,7 @@ pub struct LanguageInfo {
 }
 
 /// Information from code execution.
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct ExecuteReplyContent {
     /// Status of the request.
     pub status: Status,
@@ -270,14 +554,14 @@ pub struct ExecuteReplyContent {
 }
 
 /// Response from the IOPub status messages
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct StatusContent {
     /// The state of the kernel.
     pub execution_state: ExecutionState,
 }
 
 /// Response when code is input to the kernel.
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct ExecuteInputContent {
     /// The code that was run.
     pub code: String,
@@ -286,7 +570,7 @@ pub struct ExecuteInputContent {
 }
 
 /// Response from inspecting code
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct InspectContent {
     /// Status of the request.
     pub status: Status,
@@ -299,7 +583,7 @@ pub struct InspectContent {
 }
 
 /// Response when printing to stdout/stderr.
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct StreamContent {
     /// Type of the stream.
     pub name: StreamType,
@@ -308,7 +592,7 @@ pub struct StreamContent {
 }
 
 /// Content of an error response.
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct ErrorContent {
     /// Exception name as a string.
     pub ename: String,
@@ -319,7 +603,7 @@ pub struct ErrorContent {
 }
 
 /// Content when asking for code completion.
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct CompleteContent {
     /// Status of the request.
     pub status: Status,
@@ -334,16 +618,59 @@ pub struct CompleteContent {
 }
 
 /// Content when asking for history entries.
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct HistoryContent {
     /// Status of the request.
     pub status: Status,
     /// List of history items.
-    pub history: Vec<Value>,
+    pub history: Vec<HistoryEntry>,
+}
+
+/** An entry of the kernel's input history.
+
+Kernels send each entry as a `[session, line, input]` list, or `[session, line, [input, output]]`
+when output was requested.
+*/
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(from = "RawHistoryEntry")]
+pub struct HistoryEntry {
+    /// Session number the input was run in.
+    pub session: i64,
+    /// Line number within the session.
+    pub line: i64,
+    /// The input source code.
+    pub input: String,
+    /// The output of the input, if output was requested and the input produced any.
+    pub output: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct RawHistoryEntry(i64, i64, RawHistoryCell);
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawHistoryCell {
+    Input(String),
+    InputOutput(String, Option<String>),
+}
+
+impl From<RawHistoryEntry> for HistoryEntry {
+    fn from(RawHistoryEntry(session, line, cell): RawHistoryEntry) -> Self {
+        let (input, output) = match cell {
+            RawHistoryCell::Input(input) => (input, None),
+            RawHistoryCell::InputOutput(input, output) => (input, output),
+        };
+        HistoryEntry {
+            session,
+            line,
+            input,
+            output,
+        }
+    }
 }
 
 /// Response when asking the kernel to shutdown.
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct ShutdownContent {
     /// Status of the request.
     pub status: Status,
@@ -352,7 +679,7 @@ pub struct ShutdownContent {
 }
 
 /// Response when asking for comm info.
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct CommInfoContent {
     /// Status of the request.
     pub status: Status,
@@ -360,19 +687,26 @@ pub struct CommInfoContent {
     pub comms: HashMap<String, HashMap<String, String>>,
 }
 
+/// Response when asking to interrupt the kernel.
+#[derive(Deserialize, Debug, Clone)]
+pub struct InterruptContent {
+    /// Status of the request.
+    pub status: Status,
+}
+
 /// Response when requesting to execute code.
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct ExecuteResultContent {
     /// Global execution count.
     pub execution_count: i64,
-    /// The result of the execution.
-    pub data: HashMap<String, String>,
+    /// The result of the execution, keyed by MIME type.
+    pub data: HashMap<String, Value>,
     /// Metadata about the execution.
     pub metadata: Value,
 }
 
 /// Response when the kernel asks the client to clear the output.
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct ClearOutputContent {
     /// Wait to clear the output until new output is available.
     pub wait: bool,
@@ -380,24 +714,86 @@ pub struct ClearOutputContent {
 
 /// Response when the kernel asks the client to display some data (@l-yc)
 /// Adapted directly from https://jupyter-protocol.readthedocs.io/en/latest/messaging.html
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct DisplayDataContent {
     /// The data dict contains key/value pairs, where the keys are MIME
     /// types and the values are the raw data of the representation in that
     /// format.
-    pub data: HashMap<String, String>,
+    pub data: HashMap<String, Value>,
 
     /// Any metadata that describes the data
-    pub metadata: HashMap<String, String>,
+    pub metadata: HashMap<String, Value>,
 
     /// Optional transient data introduced in 5.1. Information not to be
     /// persisted to a notebook or other documents. Intended to live only
     /// during a live kernel session.
-    pub transient: Option<HashMap<String, String>>,
+    #[serde(default)]
+    pub transient: Option<Transient>,
+}
+
+impl DisplayDataContent {
+    /// The display id, if the display can be updated later.
+    pub fn display_id(&self) -> Option<&str> {
+        self.transient
+            .as_ref()
+            .and_then(|t| t.display_id.as_ref())
+            .map(|id| id.as_str())
+    }
+}
+
+/// Transient data attached to a display, which is not persisted to notebooks.
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct Transient {
+    /// Id used to update this display with `update_display_data` messages.
+    pub display_id: Option<String>,
+}
+
+/// Content of an input request from the kernel.
+#[derive(Deserialize, Debug, Clone)]
+pub struct InputRequestContent {
+    /// The text to show at the prompt.
+    pub prompt: String,
+    /// Whether the input should be hidden, e.g. for `getpass()`.
+    #[serde(default)]
+    pub password: bool,
+}
+
+/// Content of a `comm_open` message.
+#[derive(Deserialize, Debug, Clone)]
+pub struct CommOpenContent {
+    /// Unique id of the comm.
+    pub comm_id: String,
+    /// Name of the target that should handle the comm.
+    pub target_name: String,
+    /// Module to load the target from, if any.
+    pub target_module: Option<String>,
+    /// Data sent with the open message.
+    #[serde(default)]
+    pub data: Value,
+}
+
+/// Content of a `comm_msg` message.
+#[derive(Deserialize, Debug, Clone)]
+pub struct CommMsgContent {
+    /// Id of the comm.
+    pub comm_id: String,
+    /// Message data.
+    #[serde(default)]
+    pub data: Value,
+}
+
+/// Content of a `comm_close` message.
+#[derive(Deserialize, Debug, Clone)]
+pub struct CommCloseContent {
+    /// Id of the comm.
+    pub comm_id: String,
+    /// Data sent with the close message.
+    #[serde(default)]
+    pub data: Value,
 }
 
 /// State of the kernel.
-#[derive(Deserialize, Debug, PartialEq)]
+#[derive(Deserialize, Debug, Clone, PartialEq)]
 #[serde(rename_all = "lowercase")]
 pub enum ExecutionState {
     /// Running code.
@@ -409,7 +805,7 @@ pub enum ExecutionState {
 }
 
 /// Status of if entered code is complete (i.e. does not need another " character).
-#[derive(Deserialize, Debug, PartialEq)]
+#[derive(Deserialize, Debug, Clone, PartialEq)]
 #[serde(rename_all = "lowercase")]
 pub enum IsCompleteStatus {
     /// Entered code is complete.
@@ -423,7 +819,7 @@ pub enum IsCompleteStatus {
 }
 
 /// Type of stream, either stdout or stderr.
-#[derive(Deserialize, Debug, PartialEq)]
+#[derive(Deserialize, Debug, Clone, PartialEq)]
 #[serde(rename_all = "lowercase")]
 #[allow(missing_docs)]
 pub enum StreamType {
@@ -432,7 +828,7 @@ pub enum StreamType {
 }
 
 /// Status of the request.
-#[derive(Deserialize, Debug, PartialEq)]
+#[derive(Deserialize, Debug, Clone, PartialEq)]
 #[serde(rename_all = "lowercase")]
 #[allow(missing_docs)]
 pub enum Status {
@@ -509,6 +905,7 @@ mod tests {
                 parent_header: _parent_header,
                 metadata: _metadata,
                 content,
+                ..
             }) => {
                 // Check the header
                 assert_eq!(header.msg_type, "kernel_info_reply");
@@ -578,6 +975,7 @@ mod tests {
                 parent_header: _parent_header,
                 metadata: _metadata,
                 content,
+                ..
             }) => {
                 // Check the header
                 assert_eq!(header.msg_type, "execute_reply");
@@ -636,6 +1034,7 @@ mod tests {
                 parent_header: _parent_header,
                 metadata: _metadata,
                 content,
+                ..
             }) => {
                 // Check the header
                 assert_eq!(header.msg_type, "status");
@@ -647,6 +1046,52 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_status_without_parent_parsing() {
+        let auth = FakeAuth::create();
+        let raw_response = vec![
+            "<IDS|MSG>".to_string().into_bytes(),
+            expected_signature().into_bytes(),
+            // Header
+            r#"{
+                "date": "",
+                "msg_id": "",
+                "username": "",
+                "session": "",
+                "msg_type": "status",
+                "version": ""
+            }"#
+            .to_string()
+            .into_bytes(),
+            // Parent header, empty as the kernel is starting up
+            r#"{}"#.to_string().into_bytes(),
+            // Metadata
+            r#"{"engine": "abc"}"#.to_string().into_bytes(),
+            // Content
+            r#"{
+                "execution_state": "starting"
+            }"#
+            .to_string()
+            .into_bytes(),
+        ];
+        let msg = WireMessage::from_raw_response(raw_response, auth.clone()).unwrap();
+        let response = msg.into_response().unwrap();
+        assert!(response.parent_header().is_none());
+        match response {
+            Response::IoPub(IoPubResponse::Status {
+                parent_header,
+                metadata,
+                content,
+                ..
+            }) => {
+                assert!(parent_header.is_none());
+                assert_eq!(metadata.engine(), Some("abc"));
+                assert_eq!(content.execution_state, ExecutionState::Starting);
+            }
+            _ => unreachable!("Incorrect response type, should be Status"),
+        }
+    }
+
     #[test]
     fn test_execute_input_parsing() {
         let auth = FakeAuth::create();
@@ -694,6 +1139,7 @@ mod tests {
                 parent_header: _parent_header,
                 metadata: _metadata,
                 content,
+                ..
             }) => {
                 // Check the header
                 assert_eq!(header.msg_type, "execute_input");
@@ -753,6 +1199,7 @@ mod tests {
                 parent_header: _parent_header,
                 metadata: _metadata,
                 content,
+                ..
             }) => {
                 // Check the header
                 assert_eq!(header.msg_type, "stream");
@@ -810,6 +1257,7 @@ mod tests {
                 parent_header: _parent_header,
                 metadata: _metadata,
                 content,
+                ..
             }) => {
                 // Check the header
                 assert_eq!(header.msg_type, "is_complete_reply");
@@ -867,6 +1315,7 @@ mod tests {
                 parent_header: _parent_header,
                 metadata: _metadata,
                 content,
+                ..
             }) => {
                 // Check the header
                 assert_eq!(header.msg_type, "is_complete_reply");
@@ -878,6 +1327,50 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_is_complete_message_parsing_with_unexpected_status() {
+        let auth = FakeAuth::create();
+        let raw_response = vec![
+            "<IDS|MSG>".to_string().into_bytes(),
+            expected_signature().into_bytes(),
+            // Header
+            r#"{
+                "date": "",
+                "msg_id": "",
+                "username": "",
+                "session": "",
+                "msg_type": "is_complete_reply",
+                "version": ""
+            }"#
+            .to_string()
+            .into_bytes(),
+            // Parent header
+            r#"{
+                "date": "",
+                "msg_id": "",
+                "username": "",
+                "session": "",
+                "msg_type": "is_complete_request",
+                "version": ""
+            }"#
+            .to_string()
+            .into_bytes(),
+            // Metadata
+            r#"{}"#.to_string().into_bytes(),
+            // Content
+            r#"{
+                "status": "maybe"
+            }"#
+            .to_string()
+            .into_bytes(),
+        ];
+        let msg = WireMessage::from_raw_response(raw_response, auth.clone()).unwrap();
+        assert!(matches!(
+            msg.into_response(),
+            Err(crate::errors::Error::Protocol(_))
+        ));
+    }
+
     #[test]
     fn test_shutdown_message_parsing() {
         let auth = FakeAuth::create();
@@ -924,17 +1417,93 @@ mod tests {
                 parent_header: _parent_header,
                 metadata: _metadata,
                 content,
+                ..
             }) => {
                 // Check the header
                 assert_eq!(header.msg_type, "shutdown_reply");
 
                 // Check the content
-                assert_eq!(content.restart, false);
+                assert!(!content.restart);
             }
             _ => unreachable!("Incorrect response type, should be KernelInfo"),
         }
     }
 
+    #[test]
+    fn test_history_message_parsing() {
+        let auth = FakeAuth::create();
+        let raw_response = vec![
+            "<IDS|MSG>".to_string().into_bytes(),
+            expected_signature().into_bytes(),
+            // Header
+            r#"{
+                "date": "",
+                "msg_id": "",
+                "username": "",
+                "session": "",
+                "msg_type": "history_reply",
+                "version": ""
+            }"#
+            .to_string()
+            .into_bytes(),
+            // Parent header
+            r#"{
+                "date": "",
+                "msg_id": "",
+                "username": "",
+                "session": "",
+                "msg_type": "history_request",
+                "version": ""
+            }"#
+            .to_string()
+            .into_bytes(),
+            // Metadata
+            r#"{}"#.to_string().into_bytes(),
+            // Content
+            r#"{
+                "status": "ok",
+                "history": [
+                    [1, 1, "a = 1"],
+                    [1, 2, ["a", "1"]],
+                    [2, 1, ["print(a)", null]]
+                ]
+            }"#
+            .to_string()
+            .into_bytes(),
+        ];
+        let msg = WireMessage::from_raw_response(raw_response, auth.clone()).unwrap();
+        let response = msg.into_response().unwrap();
+        match response {
+            Response::Shell(ShellResponse::History { content, .. }) => {
+                assert_eq!(content.status, Status::Ok);
+                assert_eq!(
+                    content.history,
+                    vec![
+                        HistoryEntry {
+                            session: 1,
+                            line: 1,
+                            input: "a = 1".to_string(),
+                            output: None,
+                        },
+                        HistoryEntry {
+                            session: 1,
+                            line: 2,
+                            input: "a".to_string(),
+                            output: Some("1".to_string()),
+                        },
+                        HistoryEntry {
+                            session: 2,
+                            line: 1,
+                            input: "print(a)".to_string(),
+                            output: None,
+                        },
+                    ]
+                );
+            }
+            _ => unreachable!("Incorrect response type, should be History"),
+        }
+    }
+
     #[test]
     fn test_comm_info_message_parsing() {
         let auth = FakeAuth::create();
@@ -985,6 +1554,7 @@ mod tests {
                 parent_header: _parent_header,
                 metadata: _metadata,
                 content,
+                ..
             }) => {
                 // Check the header
                 assert_eq!(header.msg_type, "comm_info_reply");
@@ -997,9 +1567,7 @@ mod tests {
     }
 
     #[test]
-    fn test_execute_result_message_parsing() {
-        use serde_json::json;
-
+    fn test_interrupt_message_parsing() {
         let auth = FakeAuth::create();
         let raw_response = vec![
             "<IDS|MSG>".to_string().into_bytes(),
@@ -1010,7 +1578,7 @@ mod tests {
                 "msg_id": "",
                 "username": "",
                 "session": "",
-                "msg_type": "execute_result",
+                "msg_type": "interrupt_reply",
                 "version": ""
             }"#
             .to_string()
@@ -1021,7 +1589,7 @@ mod tests {
                 "msg_id": "",
                 "username": "",
                 "session": "",
-                "msg_type": "execute_request",
+                "msg_type": "interrupt_request",
                 "version": ""
             }"#
             .to_string()
@@ -1030,11 +1598,7 @@ mod tests {
             r#"{}"#.to_string().into_bytes(),
             // Content
             r#"{
-                "data": {
-                    "text/plain": "10"
-                },
-                "metadata": {},
-                "execution_count": 46
+                "status": "ok"
             }"#
             .to_string()
             .into_bytes(),
@@ -1042,26 +1606,27 @@ mod tests {
         let msg = WireMessage::from_raw_response(raw_response, auth.clone()).unwrap();
         let response = msg.into_response().unwrap();
         match response {
-            Response::IoPub(IoPubResponse::ExecuteResult {
+            Response::Shell(ShellResponse::Interrupt {
                 header,
                 parent_header: _parent_header,
                 metadata: _metadata,
                 content,
+                ..
             }) => {
                 // Check the header
-                assert_eq!(header.msg_type, "execute_result");
+                assert_eq!(header.msg_type, "interrupt_reply");
 
                 // Check the content
-                assert_eq!(content.data["text/plain"], "10");
-                assert_eq!(content.metadata, json!({}));
-                assert_eq!(content.execution_count, 46);
+                assert_eq!(content.status, Status::Ok);
             }
-            _ => unreachable!("Incorrect response type, should be ExecuteResult"),
+            _ => unreachable!("Incorrect response type, should be Interrupt"),
         }
     }
 
     #[test]
-    fn test_clear_output_message_parsing() {
+    fn test_execute_result_message_parsing() {
+        use serde_json::json;
+
         let auth = FakeAuth::create();
         let raw_response = vec![
             "<IDS|MSG>".to_string().into_bytes(),
@@ -1072,7 +1637,7 @@ mod tests {
                 "msg_id": "",
                 "username": "",
                 "session": "",
-                "msg_type": "clear_output",
+                "msg_type": "execute_result",
                 "version": ""
             }"#
             .to_string()
@@ -1092,7 +1657,11 @@ mod tests {
             r#"{}"#.to_string().into_bytes(),
             // Content
             r#"{
-                "wait": false
+                "data": {
+                    "text/plain": "10"
+                },
+                "metadata": {},
+                "execution_count": 46
             }"#
             .to_string()
             .into_bytes(),
@@ -1100,19 +1669,408 @@ mod tests {
         let msg = WireMessage::from_raw_response(raw_response, auth.clone()).unwrap();
         let response = msg.into_response().unwrap();
         match response {
-            Response::IoPub(IoPubResponse::ClearOutput {
+            Response::IoPub(IoPubResponse::ExecuteResult {
                 header,
                 parent_header: _parent_header,
                 metadata: _metadata,
                 content,
+                ..
             }) => {
                 // Check the header
-                assert_eq!(header.msg_type, "clear_output");
+                assert_eq!(header.msg_type, "execute_result");
 
                 // Check the content
-                assert_eq!(content.wait, false);
+                assert_eq!(content.data["text/plain"], "10");
+                assert_eq!(content.metadata, json!({}));
+                assert_eq!(content.execution_count, 46);
             }
-            _ => unreachable!("Incorrect response type, should be ClearOutput"),
+            _ => unreachable!("Incorrect response type, should be ExecuteResult"),
+        }
+    }
+
+    #[test]
+    fn test_clear_output_message_parsing() {
+        let auth = FakeAuth::create();
+        let raw_response = vec![
+            "<IDS|MSG>".to_string().into_bytes(),
+            expected_signature().into_bytes(),
+            // Header
+            r#"{
+                "date": "",
+                "msg_id": "",
+                "username": "",
+                "session": "",
+                "msg_type": "clear_output",
+                "version": ""
+            }"#
+            .to_string()
+            .into_bytes(),
+            // Parent header
+            r#"{
+                "date": "",
+                "msg_id": "",
+                "username": "",
+                "session": "",
+                "msg_type": "execute_request",
+                "version": ""
+            }"#
+            .to_string()
+            .into_bytes(),
+            // Metadata
+            r#"{}"#.to_string().into_bytes(),
+            // Content
+            r#"{
+                "wait": false
+            }"#
+            .to_string()
+            .into_bytes(),
+        ];
+        let msg = WireMessage::from_raw_response(raw_response, auth.clone()).unwrap();
+        let response = msg.into_response().unwrap();
+        match response {
+            Response::IoPub(IoPubResponse::ClearOutput {
+                header,
+                parent_header: _parent_header,
+                metadata: _metadata,
+                content,
+                ..
+            }) => {
+                // Check the header
+                assert_eq!(header.msg_type, "clear_output");
+
+                // Check the content
+                assert!(!content.wait);
+            }
+            _ => unreachable!("Incorrect response type, should be ClearOutput"),
+        }
+    }
+
+    #[test]
+    fn test_update_display_data_message_parsing() {
+        use serde_json::json;
+
+        let auth = FakeAuth::create();
+        let raw_response = vec![
+            "<IDS|MSG>".to_string().into_bytes(),
+            expected_signature().into_bytes(),
+            // Header
+            r#"{
+                "date": "",
+                "msg_id": "",
+                "username": "",
+                "session": "",
+                "msg_type": "update_display_data",
+                "version": ""
+            }"#
+            .to_string()
+            .into_bytes(),
+            // Parent header
+            r#"{
+                "date": "",
+                "msg_id": "",
+                "username": "",
+                "session": "",
+                "msg_type": "execute_request",
+                "version": ""
+            }"#
+            .to_string()
+            .into_bytes(),
+            // Metadata
+            r#"{}"#.to_string().into_bytes(),
+            // Content
+            r#"{
+                "data": {
+                    "text/plain": "50%",
+                    "application/json": {"progress": 0.5}
+                },
+                "metadata": {
+                    "image/png": {"width": 640}
+                },
+                "transient": {
+                    "display_id": "progress"
+                }
+            }"#
+            .to_string()
+            .into_bytes(),
+        ];
+        let msg = WireMessage::from_raw_response(raw_response, auth.clone()).unwrap();
+        let response = msg.into_response().unwrap();
+        match response {
+            Response::IoPub(IoPubResponse::UpdateDisplayData {
+                header,
+                parent_header: _parent_header,
+                metadata: _metadata,
+                content,
+                ..
+            }) => {
+                // Check the header
+                assert_eq!(header.msg_type, "update_display_data");
+
+                // Check the content
+                assert_eq!(content.display_id(), Some("progress"));
+                assert_eq!(content.data["text/plain"], "50%");
+                assert_eq!(content.data["application/json"], json!({"progress": 0.5}));
+                assert_eq!(content.metadata["image/png"], json!({"width": 640}));
+            }
+            _ => unreachable!("Incorrect response type, should be UpdateDisplayData"),
+        }
+    }
+
+    #[test]
+    fn test_unknown_message_parsing() {
+        use serde_json::json;
+
+        let auth = FakeAuth::create();
+        let raw_response = vec![
+            "<IDS|MSG>".to_string().into_bytes(),
+            expected_signature().into_bytes(),
+            // Header
+            r#"{
+                "date": "",
+                "msg_id": "",
+                "username": "",
+                "session": "",
+                "msg_type": "debug_event",
+                "version": ""
+            }"#
+            .to_string()
+            .into_bytes(),
+            // Parent header
+            r#"{
+                "date": "",
+                "msg_id": "",
+                "username": "",
+                "session": "",
+                "msg_type": "debug_request",
+                "version": ""
+            }"#
+            .to_string()
+            .into_bytes(),
+            // Metadata
+            r#"{"vendor": true}"#.to_string().into_bytes(),
+            // Content
+            r#"{
+                "seq": 1,
+                "type": "event",
+                "event": "stopped"
+            }"#
+            .to_string()
+            .into_bytes(),
+        ];
+        let msg = WireMessage::from_raw_response(raw_response, auth.clone()).unwrap();
+        let response = msg.into_response().unwrap();
+        assert_eq!(
+            response.parent_header().map(|p| p.msg_type.as_str()),
+            Some("debug_request")
+        );
+        match response {
+            Response::Unknown {
+                header,
+                parent_header,
+                metadata,
+                content,
+                buffers,
+                identities,
+            } => {
+                // Check the header
+                assert_eq!(header.msg_type, "debug_event");
+
+                // Check the raw parts
+                assert_eq!(parent_header.unwrap().msg_type, "debug_request");
+                assert_eq!(metadata, json!({"vendor": true}));
+                assert_eq!(content["event"], "stopped");
+                assert!(buffers.is_empty());
+                assert!(identities.is_empty());
+            }
+            _ => unreachable!("Incorrect response type, should be Unknown"),
+        }
+    }
+
+    #[test]
+    fn test_comm_open_message_parsing() {
+        use serde_json::json;
+
+        let auth = FakeAuth::create();
+        let raw_response = vec![
+            "<IDS|MSG>".to_string().into_bytes(),
+            expected_signature().into_bytes(),
+            // Header
+            r#"{
+                "date": "",
+                "msg_id": "",
+                "username": "",
+                "session": "",
+                "msg_type": "comm_open",
+                "version": ""
+            }"#
+            .to_string()
+            .into_bytes(),
+            // Parent header
+            r#"{
+                "date": "",
+                "msg_id": "",
+                "username": "",
+                "session": "",
+                "msg_type": "execute_request",
+                "version": ""
+            }"#
+            .to_string()
+            .into_bytes(),
+            // Metadata
+            r#"{}"#.to_string().into_bytes(),
+            // Content
+            r#"{
+                "comm_id": "u-u-i-d",
+                "target_name": "foobar",
+                "data": {"a": 1}
+            }"#
+            .to_string()
+            .into_bytes(),
+        ];
+        let msg = WireMessage::from_raw_response(raw_response, auth.clone()).unwrap();
+        let response = msg.into_response().unwrap();
+        match response {
+            Response::IoPub(IoPubResponse::CommOpen {
+                header,
+                parent_header: _parent_header,
+                metadata: _metadata,
+                content,
+                ..
+            }) => {
+                // Check the header
+                assert_eq!(header.msg_type, "comm_open");
+
+                // Check the content
+                assert_eq!(content.comm_id, "u-u-i-d");
+                assert_eq!(content.target_name, "foobar");
+                assert_eq!(content.target_module, None);
+                assert_eq!(content.data, json!({"a": 1}));
+            }
+            _ => unreachable!("Incorrect response type, should be CommOpen"),
+        }
+    }
+
+    #[test]
+    fn test_comm_msg_message_parsing() {
+        use serde_json::json;
+
+        let auth = FakeAuth::create();
+        let raw_response = vec![
+            "<IDS|MSG>".to_string().into_bytes(),
+            expected_signature().into_bytes(),
+            // Header
+            r#"{
+                "date": "",
+                "msg_id": "",
+                "username": "",
+                "session": "",
+                "msg_type": "comm_msg",
+                "version": ""
+            }"#
+            .to_string()
+            .into_bytes(),
+            // Parent header
+            r#"{
+                "date": "",
+                "msg_id": "",
+                "username": "",
+                "session": "",
+                "msg_type": "comm_msg",
+                "version": ""
+            }"#
+            .to_string()
+            .into_bytes(),
+            // Metadata
+            r#"{}"#.to_string().into_bytes(),
+            // Content
+            r#"{
+                "comm_id": "u-u-i-d",
+                "data": {"value": 10}
+            }"#
+            .to_string()
+            .into_bytes(),
+        ];
+        let msg = WireMessage::from_raw_response(raw_response, auth.clone()).unwrap();
+        let response = msg.into_response().unwrap();
+        match response {
+            Response::IoPub(IoPubResponse::CommMsg {
+                header,
+                parent_header: _parent_header,
+                metadata: _metadata,
+                content,
+                ..
+            }) => {
+                // Check the header
+                assert_eq!(header.msg_type, "comm_msg");
+
+                // Check the content
+                assert_eq!(content.comm_id, "u-u-i-d");
+                assert_eq!(content.data, json!({"value": 10}));
+            }
+            _ => unreachable!("Incorrect response type, should be CommMsg"),
+        }
+    }
+
+    #[test]
+    fn test_input_request_message_parsing() {
+        let auth = FakeAuth::create();
+        let raw_response = vec![
+            b"kernel".to_vec(),
+            "<IDS|MSG>".to_string().into_bytes(),
+            expected_signature().into_bytes(),
+            // Header
+            r#"{
+                "date": "",
+                "msg_id": "",
+                "username": "",
+                "session": "",
+                "msg_type": "input_request",
+                "version": ""
+            }"#
+            .to_string()
+            .into_bytes(),
+            // Parent header
+            r#"{
+                "date": "",
+                "msg_id": "",
+                "username": "",
+                "session": "",
+                "msg_type": "execute_request",
+                "version": ""
+            }"#
+            .to_string()
+            .into_bytes(),
+            // Metadata
+            r#"{}"#.to_string().into_bytes(),
+            // Content
+            r#"{
+                "prompt": "Password: ",
+                "password": true
+            }"#
+            .to_string()
+            .into_bytes(),
+        ];
+        let msg = WireMessage::from_raw_response(raw_response, auth.clone()).unwrap();
+        let response = msg.into_response().unwrap();
+        match response {
+            Response::Stdin(StdinResponse::InputRequest {
+                header,
+                parent_header,
+                metadata: _metadata,
+                content,
+                identities,
+                ..
+            }) => {
+                // Check the header
+                assert_eq!(header.msg_type, "input_request");
+                assert_eq!(parent_header.unwrap().msg_type, "execute_request");
+
+                // Check the content
+                assert_eq!(content.prompt, "Password: ");
+                assert!(content.password);
+                assert_eq!(identities, vec![b"kernel".to_vec()]);
+            }
+            _ => unreachable!("Incorrect response type, should be InputRequest"),
         }
     }
 }
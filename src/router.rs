@@ -0,0 +1,262 @@
+/*! Background routing of replies on the shell and control channels.
+
+Each channel is a DEALER socket owned by a background thread. Requests are handed to the thread
+over an inproc socket, and replies are matched to the waiting caller by the `msg_id` of their
+parent header, so any number of requests can be in flight at once.
+*/
+use crate::background::{self, lock, SHUTDOWN};
+use crate::errors::{Error, Result};
+use crate::responses::Response;
+use crate::signatures::Auth;
+use crate::socket::Socket;
+use crate::wire::{WireMessage, DELIMITER};
+use log::{debug, error, trace};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// Callback receiving the reply to a request, run on the routing thread.
+pub(crate) type Waiter = Box<dyn FnOnce(Result<Response>) + Send>;
+type Waiters = HashMap<String, Waiter>;
+/// Waiters by request `msg_id`, or `None` once the background thread has stopped.
+type Pending = Arc<Mutex<Option<Waiters>>>;
+
+pub(crate) struct Router {
+    requests: Mutex<zmq::Socket>,
+    pending: Pending,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Router {
+    /// Start routing messages on `socket`, which must be a DEALER socket.
+    pub(crate) fn new(ctx: &zmq::Context, socket: Socket, auth: Auth) -> Result<Router> {
+        let (incoming, requests) = background::pair(ctx, "router")?;
+
+        let pending = Arc::new(Mutex::new(Some(Waiters::new())));
+        let thread = {
+            let pending = pending.clone();
+            thread::spawn(move || route(socket, incoming, auth, pending))
+        };
+
+        Ok(Router {
+            requests: Mutex::new(requests),
+            pending,
+            thread: Some(thread),
+        })
+    }
+
+    /// Send a message without waiting for a reply.
+    pub(crate) fn send(&self, wire: WireMessage<Auth>) -> Result<()> {
+        let packets = wire.into_packets()?;
+        background::send(&lock(&self.requests), packets)
+    }
+
+    /** Send a request and wait for its reply.
+
+    With a timeout, `Error::Timeout` is returned if no reply arrives in time, and the reply is
+    dropped if it arrives later.
+    */
+    pub(crate) fn request(
+        &self,
+        wire: WireMessage<Auth>,
+        timeout: Option<Duration>,
+    ) -> Result<Response> {
+        let (tx, rx) = mpsc::channel();
+        let _request = self.start(
+            wire,
+            Box::new(move |reply| {
+                let _ = tx.send(reply);
+            }),
+        )?;
+
+        match timeout {
+            Some(timeout) => rx.recv_timeout(timeout).map_err(|e| match e {
+                RecvTimeoutError::Timeout => Error::Timeout(timeout),
+                RecvTimeoutError::Disconnected => Error::KernelDead,
+            })?,
+            None => rx.recv().map_err(|_| Error::KernelDead)?,
+        }
+    }
+
+    /** Send a request, passing its reply to `waiter` when it arrives.
+
+    The waiter is forgotten when the returned guard is dropped, so a caller that stops waiting
+    does not leave it behind.
+    */
+    pub(crate) fn start(&self, wire: WireMessage<Auth>, waiter: Waiter) -> Result<InFlight<'_>> {
+        let msg_id = wire.msg_id()?;
+        // Register before sending, so a fast reply cannot arrive before we are waiting for it.
+        self.pending()
+            .as_mut()
+            .ok_or(Error::KernelDead)?
+            .insert(msg_id.clone(), waiter);
+        let request = InFlight {
+            router: self,
+            msg_id,
+        };
+        self.send(wire)?;
+        Ok(request)
+    }
+
+    fn pending(&self) -> MutexGuard<'_, Option<Waiters>> {
+        lock(&self.pending)
+    }
+}
+
+/// A request waiting for its reply.
+pub(crate) struct InFlight<'a> {
+    router: &'a Router,
+    msg_id: String,
+}
+
+impl<'a> Drop for InFlight<'a> {
+    fn drop(&mut self) {
+        if let Some(waiters) = self.router.pending().as_mut() {
+            waiters.remove(&self.msg_id);
+        }
+    }
+}
+
+impl Drop for Router {
+    fn drop(&mut self) {
+        if background::send(&lock(&self.requests), [SHUTDOWN]).is_ok() {
+            if let Some(thread) = self.thread.take() {
+                let _ = thread.join();
+            }
+        }
+    }
+}
+
+fn route(socket: Socket, incoming: zmq::Socket, auth: Auth, pending: Pending) {
+    loop {
+        let (reply_ready, request_ready) = match background::poll(&socket.0, &incoming) {
+            Ok(ready) => ready,
+            Err(e) => {
+                error!("polling failed, no longer routing replies: {}", e);
+                break;
+            }
+        };
+
+        if request_ready {
+            match incoming.recv_multipart(0) {
+                Ok(ref frames) if frames.len() == 1 && frames[0] == SHUTDOWN => break,
+                Ok(frames) => {
+                    let parts = frames.iter().map(Vec::as_slice);
+                    // Blocking here would stop replies and shutdown, e.g. when the kernel is
+                    // unreachable, so the request fails instead
+                    if let Err(e) = socket.0.send_multipart(parts, zmq::DONTWAIT) {
+                        error!("cannot send message to the kernel: {}", e);
+                        let waiter = request_msg_id(&frames)
+                            .and_then(|msg_id| lock(&pending).as_mut()?.remove(&msg_id));
+                        if let Some(waiter) = waiter {
+                            waiter(Err(match e {
+                                zmq::Error::EAGAIN => Error::KernelDead,
+                                e => e.into(),
+                            }));
+                        }
+                    }
+                }
+                Err(e) => error!("cannot receive request: {}", e),
+            }
+        }
+
+        if reply_ready {
+            let wire = match socket.recv_wire(auth.clone()) {
+                Ok(wire) => wire,
+                Err(e) => {
+                    error!("discarding message from the kernel: {}", e);
+                    continue;
+                }
+            };
+            let waiter = msg_id(&wire.parent_header).and_then(|msg_id| {
+                trace!("reply to {}", msg_id);
+                lock(&pending).as_mut()?.remove(&msg_id)
+            });
+            match waiter {
+                // The caller may have given up waiting, in which case the reply is dropped.
+                Some(waiter) => waiter(wire.into_response()),
+                None => debug!("no request waiting for this reply, dropping it"),
+            }
+        }
+    }
+
+    // Wake up any callers still waiting, and turn away new ones, as their replies will never be
+    // routed.
+    lock(&pending).take();
+}
+
+/// Read the `msg_id` of a header or parent header frame, without parsing the rest of the message.
+fn msg_id(header: &[u8]) -> Option<String> {
+    let header: Value = serde_json::from_slice(header).ok()?;
+    header.get("msg_id")?.as_str().map(String::from)
+}
+
+/// Read the `msg_id` of an outgoing message, from the header after its delimiter and signature.
+fn request_msg_id(frames: &[Vec<u8>]) -> Option<String> {
+    let delimiter = frames.iter().position(|f| f.as_slice() == DELIMITER)?;
+    msg_id(frames.get(delimiter + 2)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::Command;
+    use crate::header::Session;
+
+    /// A router whose kernel never answers.
+    fn router(ctx: &zmq::Context) -> (Router, Auth) {
+        let auth = Auth::new("hmac-sha256", b"secret").unwrap();
+        let socket = Socket(ctx.socket(zmq::DEALER).unwrap());
+        (Router::new(ctx, socket, auth.clone()).unwrap(), auth)
+    }
+
+    fn request(auth: &Auth) -> WireMessage<Auth> {
+        Command::KernelInfo
+            .into_wire(auth.clone(), &Session::default())
+            .unwrap()
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_routing_survives_signals() {
+        let ctx = zmq::Context::new();
+        let (router, auth) = router(&ctx);
+        crate::test_helpers::signal_thread(router.thread.as_ref().unwrap());
+        router.send(request(&auth)).unwrap();
+        assert!(!router.thread.as_ref().unwrap().is_finished());
+    }
+
+    #[test]
+    fn test_stopped_thread() {
+        let ctx = zmq::Context::new();
+        let (mut router, auth) = router(&ctx);
+        lock(&router.requests).send(SHUTDOWN, 0).unwrap();
+        router.thread.take().unwrap().join().unwrap();
+
+        // Neither sending nor dropping waits for the thread
+        match router.request(request(&auth), None) {
+            Err(Error::KernelDead) => {}
+            other => unreachable!("expected a dead kernel, got {:?}", other),
+        }
+        drop(router);
+    }
+
+    #[test]
+    fn test_msg_id() {
+        assert_eq!(
+            msg_id(br#"{"msg_id": "abc", "msg_type": "execute_request"}"#),
+            Some("abc".to_string())
+        );
+        assert_eq!(msg_id(b"{}"), None);
+        assert_eq!(msg_id(b"not json"), None);
+
+        let auth = Auth::new("hmac-sha256", b"secret").unwrap();
+        let wire = request(&auth);
+        let expected = wire.msg_id().unwrap();
+        let frames: Vec<Vec<u8>> = wire.into_packets().unwrap();
+        assert_eq!(request_msg_id(&frames), Some(expected));
+    }
+}
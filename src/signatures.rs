@@ -1,7 +1,155 @@
+use crate::errors::{Error, Result};
 use hmac::{Hmac, Mac};
-use sha2::Sha256;
+use sha1::Sha1;
+use sha2::{Sha256, Sha512};
+use std::collections::{HashSet, VecDeque};
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+use subtle::ConstantTimeEq;
 
+pub(crate) type HmacSha1 = Hmac<Sha1>;
 pub(crate) type HmacSha256 = Hmac<Sha256>;
+pub(crate) type HmacSha512 = Hmac<Sha512>;
+
+/// Number of signatures remembered to detect replayed messages, the same as ipykernel.
+const HISTORY_SIZE: usize = 1 << 16;
+
+/// Something that can sign and verify the frames of a message.
+pub(crate) trait Signer: Clone + Debug {
+    /// Compute the signature of the frames.
+    fn signature<S: SignComputable>(&self, msg_list: S) -> String;
+
+    /// Check a signature received with the frames.
+    fn verify<S: SignComputable>(&self, msg_list: S, signature: &[u8]) -> Result<()> {
+        if !constant_time_eq(self.signature(msg_list).as_bytes(), signature) {
+            return Err(Error::SignatureMismatch);
+        }
+        Ok(())
+    }
+}
+
+/// Compare two byte strings in time that only depends on their lengths.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.ct_eq(b).into()
+}
+
+/** Bounded set of signatures already received.
+
+Once full, the oldest signatures are forgotten first.
+*/
+#[derive(Debug)]
+pub(crate) struct SignatureHistory {
+    seen: HashSet<Vec<u8>>,
+    order: VecDeque<Vec<u8>>,
+    capacity: usize,
+}
+
+impl SignatureHistory {
+    pub(crate) fn with_capacity(capacity: usize) -> Self {
+        SignatureHistory {
+            seen: HashSet::with_capacity(capacity),
+            order: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Record a signature, returning `false` if it has already been seen.
+    pub(crate) fn insert(&mut self, signature: &[u8]) -> bool {
+        if self.seen.contains(signature) {
+            return false;
+        }
+        if self.order.len() >= self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.seen.remove(&oldest);
+            }
+        }
+        self.seen.insert(signature.to_vec());
+        self.order.push_back(signature.to_vec());
+        true
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Scheme {
+    None,
+    Sha1(HmacSha1),
+    Sha256(HmacSha256),
+    Sha512(Box<HmacSha512>),
+}
+
+/** Message authentication, chosen from the `signature_scheme` and `key` of a connection file.
+
+An empty key means messages are not signed, and signatures are not checked. Clones share the
+history of received signatures, so a message replayed on any socket of a client is rejected.
+*/
+#[derive(Clone, Debug)]
+pub(crate) struct Auth {
+    scheme: Scheme,
+    history: Arc<Mutex<SignatureHistory>>,
+}
+
+impl Auth {
+    pub(crate) fn new(signature_scheme: &str, key: &[u8]) -> Result<Auth> {
+        let scheme = if key.is_empty() {
+            Scheme::None
+        } else {
+            let invalid_key = |e| Error::Config(format!("cannot construct HMAC: {:?}", e));
+            match signature_scheme {
+                "hmac-sha1" => HmacSha1::new_varkey(key)
+                    .map(Scheme::Sha1)
+                    .map_err(invalid_key)?,
+                "hmac-sha256" => HmacSha256::new_varkey(key)
+                    .map(Scheme::Sha256)
+                    .map_err(invalid_key)?,
+                "hmac-sha512" => HmacSha512::new_varkey(key)
+                    .map(|mac| Scheme::Sha512(Box::new(mac)))
+                    .map_err(invalid_key)?,
+                other => {
+                    return Err(Error::Config(format!(
+                        "unsupported signature scheme {:?}, expected one of hmac-sha1, hmac-sha256 or hmac-sha512",
+                        other
+                    )))
+                }
+            }
+        };
+
+        Ok(Auth {
+            scheme,
+            history: Arc::new(Mutex::new(SignatureHistory::with_capacity(HISTORY_SIZE))),
+        })
+    }
+}
+
+impl Signer for Auth {
+    fn signature<S: SignComputable>(&self, msg_list: S) -> String {
+        match &self.scheme {
+            Scheme::None => String::new(),
+            Scheme::Sha1(mac) => sign(msg_list, mac.clone()),
+            Scheme::Sha256(mac) => sign(msg_list, mac.clone()),
+            Scheme::Sha512(mac) => sign(msg_list, (**mac).clone()),
+        }
+    }
+
+    fn verify<S: SignComputable>(&self, msg_list: S, signature: &[u8]) -> Result<()> {
+        if let Scheme::None = self.scheme {
+            return Ok(());
+        }
+
+        if !constant_time_eq(self.signature(msg_list).as_bytes(), signature) {
+            return Err(Error::SignatureMismatch);
+        }
+
+        // Only record signatures of genuine messages, so forged ones cannot fill the history.
+        // The history is a plain set, so it is still usable if another thread panicked.
+        let mut history = self.history.lock().unwrap_or_else(|e| e.into_inner());
+        if !history.insert(signature) {
+            return Err(Error::DuplicateSignature {
+                signature: String::from_utf8_lossy(signature).into_owned(),
+            });
+        }
+        Ok(())
+    }
+}
 
 pub(crate) trait SignComputable {
     fn signature<M>(&self, auth: M) -> String
@@ -19,12 +167,11 @@ impl SignComputable for Vec<Vec<u8>> {
         }
         let result = auth.result();
         let code = result.code();
-        let encoded = hex::encode(code);
-        encoded
+        hex::encode(code)
     }
 }
 
-impl<'a> SignComputable for Vec<&'a [u8]> {
+impl SignComputable for Vec<&[u8]> {
     fn signature<M>(&self, mut auth: M) -> String
     where
         M: Mac,
@@ -34,8 +181,7 @@ impl<'a> SignComputable for Vec<&'a [u8]> {
         }
         let result = auth.result();
         let code = result.code();
-        let encoded = hex::encode(code);
-        encoded
+        hex::encode(code)
     }
 }
 impl<'a> SignComputable for &'a [&'a [u8]] {
@@ -48,12 +194,11 @@ impl<'a> SignComputable for &'a [&'a [u8]] {
         }
         let result = auth.result();
         let code = result.code();
-        let encoded = hex::encode(code);
-        encoded
+        hex::encode(code)
     }
 }
 
-impl<'a> SignComputable for &'a [Vec<u8>] {
+impl SignComputable for &[Vec<u8>] {
     fn signature<M>(&self, mut auth: M) -> String
     where
         M: Mac,
@@ -63,8 +208,7 @@ impl<'a> SignComputable for &'a [Vec<u8>] {
         }
         let result = auth.result();
         let code = result.code();
-        let encoded = hex::encode(code);
-        encoded
+        hex::encode(code)
     }
 }
 
@@ -90,4 +234,76 @@ mod tests {
             "77d67cc5dee7cc59a379f373432c9eb6d4183225f384ee84494cad997fd22c2a"
         );
     }
+
+    #[test]
+    fn test_signature_schemes() {
+        let data = vec![&b"a"[..], b"b"];
+
+        let auth = Auth::new("hmac-sha1", b"foobar").unwrap();
+        assert_eq!(
+            auth.signature(data.clone()),
+            "89d421ce671ed416b73dd199d43c66842f230008"
+        );
+
+        let auth = Auth::new("hmac-sha256", b"foobar").unwrap();
+        assert_eq!(
+            auth.signature(data.clone()),
+            "77d67cc5dee7cc59a379f373432c9eb6d4183225f384ee84494cad997fd22c2a"
+        );
+
+        let auth = Auth::new("hmac-sha512", b"foobar").unwrap();
+        assert_eq!(
+            auth.signature(data),
+            "564df3783bd9b596944568221f0f3b186077780bfbbf6ca5d9824590d0340d776bc30d2e9196fbe9f0211b6cc3beb216ff3cd3fbe796939619402b6cc833f6f5"
+        );
+    }
+
+    #[test]
+    fn test_empty_key_disables_signing() {
+        let auth = Auth::new("hmac-sha256", b"").unwrap();
+        let data = vec![&b"a"[..], b"b"];
+        assert_eq!(auth.signature(data.clone()), "");
+        assert!(auth.verify(data.clone(), b"anything").is_ok());
+        assert!(auth.verify(data, b"anything").is_ok());
+    }
+
+    #[test]
+    fn test_verify_rejects_bad_and_replayed_signatures() {
+        let auth = Auth::new("hmac-sha256", b"foobar").unwrap();
+        let data = vec![&b"a"[..], b"b"];
+        let signature = auth.signature(data.clone());
+
+        assert!(matches!(
+            auth.verify(data.clone(), b"77d67cc5"),
+            Err(Error::SignatureMismatch)
+        ));
+        assert!(auth.verify(data.clone(), signature.as_bytes()).is_ok());
+
+        // Clones share the history
+        let err = auth.clone().verify(data, signature.as_bytes()).unwrap_err();
+        match err {
+            Error::DuplicateSignature { signature: s } => assert_eq!(s, signature),
+            e => unreachable!("expected a duplicate signature error, got {:?}", e),
+        }
+    }
+
+    #[test]
+    fn test_signature_history_is_bounded() {
+        let mut history = SignatureHistory::with_capacity(2);
+        assert!(history.insert(b"a"));
+        assert!(history.insert(b"b"));
+        assert!(!history.insert(b"a"));
+        assert!(history.insert(b"c"));
+        // "a" was the oldest, and has been forgotten
+        assert!(history.insert(b"a"));
+        assert!(!history.insert(b"c"));
+    }
+
+    #[test]
+    fn test_unsupported_scheme() {
+        match Auth::new("hmac-md5", b"foobar") {
+            Err(Error::Config(msg)) => assert!(msg.contains("hmac-md5")),
+            other => unreachable!("expected a configuration error, got {:?}", other),
+        }
+    }
 }
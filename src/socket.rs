@@ -1,13 +1,13 @@
 use crate::connection_config::ConnectionConfig;
-use crate::errors::Result;
+use crate::errors::{Error, Result};
+use crate::signatures::Signer;
 use crate::wire::WireMessage;
-use hmac::Mac;
-use std::fmt::Debug;
-use zmq;
+use std::time::{Duration, Instant};
 
 pub(crate) enum SocketType {
     Shell,
     Control,
+    Stdin,
     IoPub,
     Heartbeat,
 }
@@ -15,22 +15,47 @@ pub(crate) enum SocketType {
 pub(crate) struct Socket(pub zmq::Socket);
 
 impl Socket {
-    pub fn new_shell(ctx: &zmq::Context, config: &ConnectionConfig) -> Result<Socket> {
-        let socket = ctx.socket(zmq::REQ)?;
+    /// Requests left unsent when the client is dropped, e.g. to a dead kernel, are discarded
+    /// rather than blocking the context from closing.
+    pub fn new_shell(
+        ctx: &zmq::Context,
+        config: &ConnectionConfig,
+        identity: &[u8],
+    ) -> Result<Socket> {
+        let socket = ctx.socket(zmq::DEALER)?;
+        socket.set_linger(0)?;
+        socket.set_identity(identity)?;
         let conn_str = Socket::connection_string(config, SocketType::Shell);
         socket.connect(&conn_str)?;
 
         Ok(Socket(socket))
     }
 
+    /// Like the shell socket, this does not linger.
     pub fn new_control(ctx: &zmq::Context, config: &ConnectionConfig) -> Result<Socket> {
-        let socket = ctx.socket(zmq::REQ)?;
+        let socket = ctx.socket(zmq::DEALER)?;
+        socket.set_linger(0)?;
         let conn_str = Socket::connection_string(config, SocketType::Control);
         socket.connect(&conn_str)?;
 
         Ok(Socket(socket))
     }
 
+    /// The stdin socket must share its identity with the shell socket, as the kernel routes
+    /// `input_request` messages to the peer that sent the `execute_request`.
+    pub fn new_stdin(
+        ctx: &zmq::Context,
+        config: &ConnectionConfig,
+        identity: &[u8],
+    ) -> Result<Socket> {
+        let socket = ctx.socket(zmq::DEALER)?;
+        socket.set_identity(identity)?;
+        let conn_str = Socket::connection_string(config, SocketType::Stdin);
+        socket.connect(&conn_str)?;
+
+        Ok(Socket(socket))
+    }
+
     pub fn new_iopub(ctx: &zmq::Context, config: &ConnectionConfig) -> Result<Socket> {
         let socket = ctx.socket(zmq::SUB)?;
         let conn_str = Socket::connection_string(config, SocketType::IoPub);
@@ -40,36 +65,45 @@ impl Socket {
         Ok(Socket(socket))
     }
 
+    /// A heartbeat socket that gets no reply is useless, and is replaced rather than closed
+    /// cleanly, so it does not linger.
     pub fn new_heartbeat(ctx: &zmq::Context, config: &ConnectionConfig) -> Result<Socket> {
         let socket = ctx.socket(zmq::REQ)?;
+        socket.set_linger(0)?;
         let conn_str = Socket::connection_string(config, SocketType::Heartbeat);
         socket.connect(&conn_str)?;
 
         Ok(Socket(socket))
     }
 
-    pub(crate) fn send_wire<M: Mac + Debug>(&self, wire: WireMessage<M>) -> Result<()> {
+    pub(crate) fn send_wire<M: Signer>(&self, wire: WireMessage<M>) -> Result<()> {
         let packets = wire.into_packets()?;
         let slices: Vec<_> = packets.iter().map(|v| v.as_slice()).collect();
         self.0.send_multipart(slices.as_slice(), 0)?;
         Ok(())
     }
 
-    pub(crate) fn recv_wire<M: Mac + Debug>(&self, auth: M) -> Result<WireMessage<M>> {
+    pub(crate) fn recv_wire<M: Signer>(&self, auth: M) -> Result<WireMessage<M>> {
         let raw_response = self.0.recv_multipart(0)?;
         WireMessage::from_raw_response(raw_response, auth.clone())
     }
 
-    pub(crate) fn heartbeat(&self) -> Result<()> {
-        self.0.send(b"".as_slice(), 0)?;
+    /// Send a heartbeat, and return the round trip time of the reply.
+    pub(crate) fn heartbeat(&self, timeout: Duration) -> Result<Duration> {
+        let sent = Instant::now();
+        self.0.send(b"ping".as_slice(), 0)?;
+        if self.0.poll(zmq::POLLIN, timeout.as_millis() as i64)? == 0 {
+            return Err(Error::Timeout(timeout));
+        }
         let _msg = self.0.recv_msg(0)?;
-        Ok(())
+        Ok(sent.elapsed())
     }
 
     fn connection_string(config: &ConnectionConfig, socket_type: SocketType) -> String {
         let port = match socket_type {
             SocketType::Shell => config.shell_port,
             SocketType::Control => config.control_port,
+            SocketType::Stdin => config.stdin_port,
             SocketType::IoPub => config.iopub_port,
             SocketType::Heartbeat => config.hb_port,
         };
@@ -0,0 +1,314 @@
+/*! Answering input requests from the kernel.
+
+When code running in the kernel calls e.g. Python's `input()` or `getpass()`, the kernel sends an
+`input_request` on the stdin channel and blocks until the client sends back an `input_reply`.
+Register an [`InputHandler`](trait.InputHandler.html) with
+[`Client::set_input_handler`][set-input-handler] to answer these requests.
+
+[set-input-handler]: ../struct.Client.html#method.set_input_handler
+*/
+use crate::background::{self, lock, SHUTDOWN};
+use crate::commands::Command;
+use crate::errors::{Error, Result};
+use crate::header::Session;
+use crate::responses::{Response, StdinResponse};
+use crate::signatures::Auth;
+use crate::socket::Socket;
+use log::{debug, error, warn};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+/** Handler for `input_request` messages from the kernel.
+
+The returned string is sent back to the kernel as the `value` of the `input_reply`.
+
+Any `FnMut(&str, bool) -> String` closure can be used as a handler:
+
+```no_run
+# use jupyter_client::{Result, Client};
+# fn main() -> Result<()> {
+let client = Client::existing()?;
+client.set_input_handler(|prompt: &str, _password: bool| {
+    println!("kernel asked: {}", prompt);
+    "42".to_string()
+})?;
+# Ok(())
+# }
+```
+*/
+pub trait InputHandler: Send {
+    /** Answer an input request.
+
+    `prompt` is the text to show to the user, and `password` is `true` if the input should not
+    be echoed.
+    */
+    fn handle_input(&mut self, prompt: &str, password: bool) -> String;
+}
+
+impl<F> InputHandler for F
+where
+    F: FnMut(&str, bool) -> String + Send,
+{
+    fn handle_input(&mut self, prompt: &str, password: bool) -> String {
+        self(prompt, password)
+    }
+}
+
+/** Input handler that answers with a predefined list of values.
+
+This is useful for unattended runs, where code that asks for input should be fed the same
+answers every time. Answers are given in order; once they run out, an empty string is sent so
+the kernel does not hang.
+
+```
+# use jupyter_client::stdin::{InputHandler, ScriptedResponder};
+let mut responder = ScriptedResponder::new(vec!["alice", "hunter2"]);
+assert_eq!(responder.handle_input("Name: ", false), "alice");
+assert_eq!(responder.handle_input("Password: ", true), "hunter2");
+assert_eq!(responder.handle_input("Again: ", false), "");
+```
+*/
+#[derive(Debug, Default)]
+pub struct ScriptedResponder {
+    answers: VecDeque<String>,
+}
+
+impl ScriptedResponder {
+    /// Create a responder that gives the answers in order.
+    pub fn new<I, S>(answers: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        ScriptedResponder {
+            answers: answers.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    /// Add another answer to the end of the script.
+    pub fn push<S>(&mut self, answer: S)
+    where
+        S: Into<String>,
+    {
+        self.answers.push_back(answer.into());
+    }
+
+    /// Number of answers not yet given.
+    pub fn remaining(&self) -> usize {
+        self.answers.len()
+    }
+}
+
+impl InputHandler for ScriptedResponder {
+    fn handle_input(&mut self, prompt: &str, _password: bool) -> String {
+        self.answers.pop_front().unwrap_or_else(|| {
+            warn!("no scripted answer left for prompt {:?}", prompt);
+            String::new()
+        })
+    }
+}
+
+/// The current handler, and the session its replies are sent from.
+struct Answerer {
+    handler: Box<dyn InputHandler>,
+    session: Session,
+}
+
+/// The current handler, if any, and whether the background thread has stopped.
+#[derive(Default)]
+struct Answering {
+    answerer: Option<Answerer>,
+    stopped: bool,
+}
+
+type Slot = Arc<Mutex<Answering>>;
+
+/// Sockets owned by the background thread while it runs.
+struct Sockets {
+    stdin: Socket,
+    shutdown: zmq::Socket,
+}
+
+/** Answers input requests on the stdin channel.
+
+A background thread owns the stdin socket, and passes each `input_request` to the current
+handler. It is started when the first handler is set, and stopped when this is dropped.
+*/
+pub(crate) struct StdinChannel {
+    answerer: Slot,
+    idle: Mutex<Option<Sockets>>,
+    shutdown: Mutex<zmq::Socket>,
+    thread: Mutex<Option<JoinHandle<()>>>,
+    auth: Auth,
+}
+
+impl StdinChannel {
+    /// Answer input requests received on `socket`, which must be a DEALER socket.
+    pub(crate) fn new(ctx: &zmq::Context, socket: Socket, auth: Auth) -> Result<StdinChannel> {
+        let (incoming, shutdown) = background::pair(ctx, "stdin")?;
+
+        Ok(StdinChannel {
+            answerer: Slot::default(),
+            idle: Mutex::new(Some(Sockets {
+                stdin: socket,
+                shutdown: incoming,
+            })),
+            shutdown: Mutex::new(shutdown),
+            thread: Mutex::new(None),
+            auth,
+        })
+    }
+
+    /** Answer input requests with `handler` from now on, replacing any previous handler.
+
+    Fails with `Error::KernelDead` if the background thread has stopped after an error.
+    */
+    pub(crate) fn set_handler(
+        &self,
+        handler: Box<dyn InputHandler>,
+        session: Session,
+    ) -> Result<()> {
+        {
+            let mut answering = lock(&self.answerer);
+            if answering.stopped {
+                return Err(Error::KernelDead);
+            }
+            answering.answerer = Some(Answerer { handler, session });
+        }
+        if let Some(sockets) = lock(&self.idle).take() {
+            let auth = self.auth.clone();
+            let answerer = self.answerer.clone();
+            *lock(&self.thread) = Some(thread::spawn(move || {
+                answer(sockets, auth, &answerer);
+                // Turn away new handlers, and drop the current one
+                *lock(&answerer) = Answering {
+                    answerer: None,
+                    stopped: true,
+                };
+            }));
+        }
+        Ok(())
+    }
+}
+
+impl Drop for StdinChannel {
+    fn drop(&mut self) {
+        if let Some(thread) = lock(&self.thread).take() {
+            if background::send(&lock(&self.shutdown), [SHUTDOWN]).is_ok() {
+                let _ = thread.join();
+            }
+        }
+    }
+}
+
+fn answer(sockets: Sockets, auth: Auth, answerer: &Slot) {
+    loop {
+        let (request_ready, shutdown_ready) =
+            match background::poll(&sockets.stdin.0, &sockets.shutdown) {
+                Ok(ready) => ready,
+                Err(e) => {
+                    error!("polling failed, no longer answering input requests: {}", e);
+                    return;
+                }
+            };
+
+        if shutdown_ready {
+            return;
+        }
+
+        if request_ready {
+            if let Err(e) = answer_one(&sockets.stdin, &auth, answerer) {
+                error!("error handling input request: {}", e);
+            }
+        }
+    }
+}
+
+fn answer_one(socket: &Socket, auth: &Auth, answerer: &Slot) -> Result<()> {
+    let response = socket.recv_wire(auth.clone())?.into_response()?;
+    let (header, content) = match response {
+        Response::Stdin(StdinResponse::InputRequest {
+            header, content, ..
+        }) => (header, content),
+        other => {
+            debug!("ignoring message on stdin channel: {:?}", other);
+            return Ok(());
+        }
+    };
+
+    let mut answering = lock(answerer);
+    let answerer = match answering.answerer.as_mut() {
+        Some(answerer) => answerer,
+        None => {
+            warn!("no input handler for prompt {:?}", content.prompt);
+            return Ok(());
+        }
+    };
+    let value = answerer
+        .handler
+        .handle_input(&content.prompt, content.password);
+    let mut reply = Command::InputReply { value }.into_wire(auth.clone(), &answerer.session)?;
+    reply.parent_header = header.to_bytes()?;
+    socket.send_wire(reply)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scripted_responder_answers_in_order() {
+        let mut responder = ScriptedResponder::new(vec!["a", "b"]);
+        responder.push("c");
+        assert_eq!(responder.remaining(), 3);
+        assert_eq!(responder.handle_input("", false), "a");
+        assert_eq!(responder.handle_input("", true), "b");
+        assert_eq!(responder.handle_input("", false), "c");
+        assert_eq!(responder.handle_input("", false), "");
+        assert_eq!(responder.remaining(), 0);
+    }
+
+    fn channel(ctx: &zmq::Context) -> StdinChannel {
+        let auth = Auth::new("hmac-sha256", b"secret").unwrap();
+        let socket = Socket(ctx.socket(zmq::DEALER).unwrap());
+        let channel = StdinChannel::new(ctx, socket, auth).unwrap();
+        channel
+            .set_handler(Box::new(ScriptedResponder::default()), Session::default())
+            .unwrap();
+        channel
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_answering_survives_signals() {
+        let ctx = zmq::Context::new();
+        let channel = channel(&ctx);
+        crate::test_helpers::signal_thread(lock(&channel.thread).as_ref().unwrap());
+        assert!(!lock(&channel.thread).as_ref().unwrap().is_finished());
+        channel
+            .set_handler(Box::new(ScriptedResponder::default()), Session::default())
+            .unwrap();
+    }
+
+    #[test]
+    fn test_stopped_thread() {
+        let ctx = zmq::Context::new();
+        let channel = channel(&ctx);
+        lock(&channel.shutdown).send(SHUTDOWN, 0).unwrap();
+        lock(&channel.thread).take().unwrap().join().unwrap();
+
+        match channel.set_handler(Box::new(ScriptedResponder::default()), Session::default()) {
+            Err(Error::KernelDead) => {}
+            Err(e) => unreachable!("expected a dead kernel, got {:?}", e),
+            Ok(()) => unreachable!("expected a dead kernel"),
+        }
+        drop(channel);
+    }
+
+    #[test]
+    fn test_closure_handler() {
+        let mut handler = |prompt: &str, password: bool| format!("{}{}", prompt, password);
+        assert_eq!(handler.handle_input("p", true), "ptrue");
+    }
+}
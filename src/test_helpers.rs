@@ -1,7 +1,12 @@
+use crate::header::{Header, Session};
+use crate::signatures::{sign, Auth, SignComputable, Signer};
+use crate::wire::WireMessage;
+use crate::Client;
 use crypto_mac::MacResult;
 use digest::generic_array::typenum::U64;
 use generic_array::GenericArray;
 use hmac::Mac;
+use std::path::PathBuf;
 
 #[derive(Debug, Clone)]
 pub(crate) struct FakeAuth;
@@ -23,6 +28,12 @@ impl Mac for FakeAuth {
     }
 }
 
+impl Signer for FakeAuth {
+    fn signature<S: SignComputable>(&self, msg_list: S) -> String {
+        sign(msg_list, self.clone())
+    }
+}
+
 impl FakeAuth {
     pub(crate) fn create() -> FakeAuth {
         FakeAuth::new_varkey(KEY).expect("creating fake auth object")
@@ -33,10 +44,10 @@ pub(crate) fn expected_signature() -> String {
     let auth = FakeAuth::create();
     let res = auth.result();
     let code = res.code();
-    let encoded = hex::encode(code);
-    encoded
+    hex::encode(code)
 }
 
+/// Compare two byte strings, printing them as text if they differ.
 #[macro_export]
 macro_rules! compare_bytestrings {
     ($a:expr, $b:expr) => {
@@ -45,3 +56,147 @@ macro_rules! compare_bytestrings {
         assert_eq!($a, $b, "result {:?} != expected {:?}", a, b);
     };
 }
+
+/// Kernel end of the shell, control and stdin channels, bound to random local ports.
+pub(crate) struct FakeKernel {
+    pub(crate) shell: zmq::Socket,
+    pub(crate) control: zmq::Socket,
+    pub(crate) stdin: zmq::Socket,
+    pub(crate) iopub: zmq::Socket,
+    pub(crate) heartbeat: zmq::Socket,
+    pub(crate) auth: Auth,
+    _ctx: zmq::Context,
+}
+
+fn bind(ctx: &zmq::Context, kind: zmq::SocketType) -> zmq::Socket {
+    let socket = ctx.socket(kind).unwrap();
+    socket.set_linger(0).unwrap();
+    socket.bind("tcp://127.0.0.1:*").unwrap();
+    socket
+}
+
+fn port(socket: &zmq::Socket) -> u32 {
+    let endpoint = socket.get_last_endpoint().unwrap().unwrap();
+    endpoint.rsplit(':').next().unwrap().parse().unwrap()
+}
+
+impl FakeKernel {
+    pub(crate) fn new() -> Self {
+        let ctx = zmq::Context::new();
+        FakeKernel {
+            shell: bind(&ctx, zmq::ROUTER),
+            control: bind(&ctx, zmq::ROUTER),
+            stdin: bind(&ctx, zmq::ROUTER),
+            iopub: bind(&ctx, zmq::XPUB),
+            heartbeat: bind(&ctx, zmq::REP),
+            auth: Auth::new("hmac-sha256", b"secret").unwrap(),
+            _ctx: ctx,
+        }
+    }
+
+    /// Connect a client to this kernel.
+    pub(crate) fn client(&self) -> Client {
+        let info = serde_json::json!({
+            "shell_port": port(&self.shell),
+            "iopub_port": port(&self.iopub),
+            "stdin_port": port(&self.stdin),
+            "control_port": port(&self.control),
+            "hb_port": port(&self.heartbeat),
+            "ip": "127.0.0.1",
+            "key": "secret",
+            "transport": "tcp",
+            "signature_scheme": "hmac-sha256",
+            "kernel_name": "fake",
+        });
+        Client::from_reader(info.to_string().as_bytes()).unwrap()
+    }
+
+    /// Receive a message sent by the client.
+    pub(crate) fn recv(&self, socket: &zmq::Socket) -> WireMessage<Auth> {
+        let raw = socket.recv_multipart(0).unwrap();
+        WireMessage::from_raw_response(raw, self.auth.clone()).unwrap()
+    }
+
+    /// Send a message caused by `request`, routed back to its sender.
+    pub(crate) fn reply(
+        &self,
+        socket: &zmq::Socket,
+        request: &WireMessage<Auth>,
+        msg_type: &str,
+        content: serde_json::Value,
+    ) {
+        let wire = WireMessage {
+            identities: request.identities.clone(),
+            header: Header::new(msg_type, &Session::default())
+                .to_bytes()
+                .unwrap(),
+            parent_header: request.header.clone(),
+            metadata: b"{}".to_vec(),
+            content: content.to_string().into_bytes(),
+            buffers: Vec::new(),
+            auth: self.auth.clone(),
+        };
+        socket
+            .send_multipart(wire.into_packets().unwrap(), 0)
+            .unwrap();
+    }
+
+    /// Block until a client has subscribed to IOPub, so that nothing published is lost.
+    pub(crate) fn wait_for_subscriber(&self) {
+        let subscription = self.iopub.recv_bytes(0).unwrap();
+        assert_eq!(subscription.first(), Some(&1));
+    }
+
+    /// Publish a message on IOPub that was not caused by any request.
+    pub(crate) fn publish(&self, msg_type: &str, content: serde_json::Value) {
+        let wire = WireMessage {
+            identities: vec![msg_type.as_bytes().to_vec()],
+            header: Header::new(msg_type, &Session::default())
+                .to_bytes()
+                .unwrap(),
+            parent_header: b"{}".to_vec(),
+            metadata: b"{}".to_vec(),
+            content: content.to_string().into_bytes(),
+            buffers: Vec::new(),
+            auth: self.auth.clone(),
+        };
+        self.iopub
+            .send_multipart(wire.into_packets().unwrap(), 0)
+            .unwrap();
+    }
+}
+
+/// A uniquely named directory under the system temporary directory, removed on drop.
+pub(crate) struct TempDir(pub(crate) PathBuf);
+
+impl TempDir {
+    pub(crate) fn new() -> Self {
+        let dir = std::env::temp_dir().join(format!("jupyter-client-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        TempDir(dir)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Interrupt a blocking call on `thread` by delivering a signal that is otherwise ignored.
+#[cfg(unix)]
+pub(crate) fn signal_thread<T>(thread: &std::thread::JoinHandle<T>) {
+    use std::os::unix::thread::JoinHandleExt;
+
+    extern "C" fn ignore(_: libc::c_int) {}
+    // Let the thread start blocking first
+    std::thread::sleep(std::time::Duration::from_millis(50));
+    unsafe {
+        libc::signal(
+            libc::SIGUSR1,
+            ignore as extern "C" fn(libc::c_int) as libc::sighandler_t,
+        );
+        assert_eq!(libc::pthread_kill(thread.as_pthread_t(), libc::SIGUSR1), 0);
+    }
+    std::thread::sleep(std::time::Duration::from_millis(50));
+}
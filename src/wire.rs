@@ -1,217 +1,361 @@
 // This file has been modified from the original by @l-yc
-use crate::errors::Result;
+use crate::errors::{Error, Result};
 use crate::header::Header;
 use crate::metadata::Metadata;
 use crate::responses::*;
-use crate::signatures::sign;
-use failure::{bail, format_err};
-use hmac::Mac;
-use log::{debug, trace};
+use crate::signatures::Signer;
+use log::{debug, trace, warn};
+use serde::de::DeserializeOwned;
 use serde_json::Value;
-use std::fmt::Debug;
+use std::sync::Arc;
 
 type Part = Vec<u8>;
 
-static DELIMITER: &[u8] = b"<IDS|MSG>";
+pub(crate) static DELIMITER: &[u8] = b"<IDS|MSG>";
 
 #[derive(Debug)]
-pub(crate) struct WireMessage<M: Mac + Debug> {
+pub(crate) struct WireMessage<M: Signer> {
+    pub(crate) identities: Vec<Part>,
     pub(crate) header: Part,
     pub(crate) parent_header: Part,
     pub(crate) metadata: Part,
     pub(crate) content: Part,
+    pub(crate) buffers: Vec<Part>,
     pub(crate) auth: M,
 }
 
-impl<M: Mac + Debug> WireMessage<M> {
+impl<M: Signer> WireMessage<M> {
     pub(crate) fn from_raw_response(raw: Vec<Vec<u8>>, auth: M) -> Result<Self> {
         trace!("raw response: {:?}", raw);
         let delim_idx = raw
             .iter()
-            .position(|r| String::from_utf8(r.to_vec()).unwrap() == "<IDS|MSG>")
-            .ok_or_else(|| format_err!("cannot find delimiter in response"))?;
+            .position(|r| r.as_slice() == DELIMITER)
+            .ok_or_else(|| {
+                Error::MalformedFrames("cannot find delimiter in response".to_string())
+            })?;
 
+        let identities = raw[..delim_idx].to_vec();
         debug!(
-            "identities: {:#?}",
-            raw[..delim_idx]
+            "identities: {:?}",
+            identities
                 .iter()
-                .map(|b| std::str::from_utf8(b))
+                .map(|b| String::from_utf8_lossy(b))
                 .collect::<Vec<_>>()
         );
 
-        // Check the signature
-        let signature = String::from_utf8_lossy(&raw[delim_idx + 1]);
-        let msg_frames = &raw[delim_idx + 2..];
-        let check_sig = sign(msg_frames, auth.clone());
-
-        if check_sig != signature {
-            bail!("signatures do not match");
+        // The signature, header, parent header, metadata and content are required, followed by
+        // any number of binary buffers.
+        if raw.len() < delim_idx + 6 {
+            return Err(Error::MalformedFrames(format!(
+                "expected at least 5 frames after the delimiter, found {}",
+                raw.len() - delim_idx - 1
+            )));
         }
 
+        // Check the signature. Buffers are not part of the signed message.
+        let signature = &raw[delim_idx + 1];
+        let msg_frames = &raw[delim_idx + 2..delim_idx + 6];
+        auth.verify(msg_frames, signature)?;
+
         Ok(WireMessage {
+            identities,
             header: msg_frames[0].clone(),
             parent_header: msg_frames[1].clone(),
             metadata: msg_frames[2].clone(),
             content: msg_frames[3].clone(),
+            buffers: raw[delim_idx + 6..].to_vec(),
             auth: auth.clone(),
         })
     }
 
-    pub(crate) fn into_response(self) -> Result<Response> {
-        let header_str = std::str::from_utf8(&self.header)?;
-        let header: Header = serde_json::from_str(header_str)?;
-        trace!("header: {:?}", header);
+    /// Id of this message, read from its header.
+    pub(crate) fn msg_id(&self) -> Result<String> {
+        let header: Header = decode(frame_str(&self.header, "header")?, None)?;
+        Ok(header.msg_id)
+    }
 
-        let parent_header_str = std::str::from_utf8(&self.parent_header)?;
-        let parent_header: Header = serde_json::from_str(parent_header_str)?;
-        trace!("parent header: {:?}", parent_header);
+    pub(crate) fn into_response(self) -> Result<Response> {
+        let buffers = self.buffers;
+        let identities = self.identities;
 
-        let metadata_str = std::str::from_utf8(&self.metadata)?;
-        let metadata: Metadata = serde_json::from_str(metadata_str)?;
+        let header: Header = decode(frame_str(&self.header, "header")?, None)?;
+        trace!("header: {:?}", header);
+        let msg_type = header.msg_type.clone();
+
+        // Messages the kernel sends on its own, such as the `starting` status, have an empty
+        // parent header. A parent header that cannot be parsed is only an error for the types
+        // handled below.
+        let parent_header_str = frame_str(&self.parent_header, "parent header")?;
+        let raw_parent_header: Value = decode(parent_header_str, Some(&msg_type))?;
+        trace!("parent header: {:?}", raw_parent_header);
+        let parent_header = parse_parent_header(&raw_parent_header, &msg_type);
+
+        let metadata_str = frame_str(&self.metadata, "metadata")?;
+        let metadata: Metadata = decode(metadata_str, Some(&msg_type))?;
         trace!("metadata: {:?}", metadata);
 
-        let content_str = std::str::from_utf8(&self.content)?;
+        let content_str = frame_str(&self.content, "content")?;
         trace!("content string: {}", content_str);
 
-        debug!("received message type `{}`", &header.msg_type);
-        match header.msg_type.as_str() {
+        debug!("received message type `{}`", msg_type);
+        match msg_type.as_str() {
             "kernel_info_reply" => Ok(Response::Shell(ShellResponse::KernelInfo {
                 header,
-                parent_header,
+                parent_header: parent_header?,
                 metadata,
-                content: serde_json::from_str(content_str)?,
+                content: decode(content_str, Some(&msg_type))?,
+                buffers,
+                identities,
             })),
             "execute_reply" => Ok(Response::Shell(ShellResponse::Execute {
                 header,
-                parent_header,
+                parent_header: parent_header?,
                 metadata,
-                content: serde_json::from_str(content_str)?,
+                content: decode(content_str, Some(&msg_type))?,
+                buffers,
+                identities,
             })),
             "inspect_reply" => Ok(Response::Shell(ShellResponse::Inspect {
                 header,
-                parent_header,
+                parent_header: parent_header?,
                 metadata,
-                content: serde_json::from_str(content_str)?,
+                content: decode(content_str, Some(&msg_type))?,
+                buffers,
+                identities,
             })),
             "complete_reply" => Ok(Response::Shell(ShellResponse::Complete {
                 header,
-                parent_header,
+                parent_header: parent_header?,
                 metadata,
-                content: serde_json::from_str(content_str)?,
+                content: decode(content_str, Some(&msg_type))?,
+                buffers,
+                identities,
             })),
             "history_reply" => Ok(Response::Shell(ShellResponse::History {
                 header,
-                parent_header,
+                parent_header: parent_header?,
                 metadata,
-                content: serde_json::from_str(content_str)?,
+                content: decode(content_str, Some(&msg_type))?,
+                buffers,
+                identities,
             })),
             "is_complete_reply" => {
-                let content_json: Value = serde_json::from_str(content_str)?;
+                let content_json: Value = decode(content_str, Some(&msg_type))?;
                 let content = match content_json["status"] {
                     Value::String(ref s) if s == "complete" => IsCompleteStatus::Complete,
                     Value::String(ref s) if s == "invalid" => IsCompleteStatus::Invalid,
                     Value::String(ref s) if s == "unknown" => IsCompleteStatus::Unknown,
                     Value::String(ref s) if s == "incomplete" => {
                         let indent_node = &content_json["indent"];
-                        let indent = String::from(
-                            indent_node
-                                .as_str()
-                                .ok_or(format_err!("response indent value empty"))?,
-                        );
+                        let indent = String::from(indent_node.as_str().ok_or_else(|| {
+                            Error::Protocol("is_complete_reply has no indent".to_string())
+                        })?);
                         IsCompleteStatus::Incomplete(indent)
                     }
-                    _ => unreachable!(),
+                    ref status => {
+                        return Err(Error::Protocol(format!(
+                            "unknown is_complete status: {}",
+                            status
+                        )))
+                    }
                 };
 
                 Ok(Response::Shell(ShellResponse::IsComplete {
                     header,
-                    parent_header,
+                    parent_header: parent_header?,
                     metadata,
-                    content: content,
+                    content,
+                    buffers,
+                    identities,
                 }))
             }
             "shutdown_reply" => Ok(Response::Shell(ShellResponse::Shutdown {
                 header,
-                parent_header,
+                parent_header: parent_header?,
                 metadata,
-                content: serde_json::from_str(content_str)?,
+                content: decode(content_str, Some(&msg_type))?,
+                buffers,
+                identities,
             })),
             "comm_info_reply" => Ok(Response::Shell(ShellResponse::CommInfo {
                 header,
-                parent_header,
+                parent_header: parent_header?,
+                metadata,
+                content: decode(content_str, Some(&msg_type))?,
+                buffers,
+                identities,
+            })),
+            "interrupt_reply" => Ok(Response::Shell(ShellResponse::Interrupt {
+                header,
+                parent_header: parent_header?,
                 metadata,
-                content: serde_json::from_str(content_str)?,
+                content: decode(content_str, Some(&msg_type))?,
+                buffers,
+                identities,
             })),
             "status" => Ok(Response::IoPub(IoPubResponse::Status {
                 header,
-                parent_header,
+                parent_header: parent_header?,
                 metadata,
-                content: serde_json::from_str(content_str)?,
+                content: decode(content_str, Some(&msg_type))?,
+                buffers,
+                identities,
             })),
             "execute_input" => Ok(Response::IoPub(IoPubResponse::ExecuteInput {
                 header,
-                parent_header,
+                parent_header: parent_header?,
                 metadata,
-                content: serde_json::from_str(content_str)?,
+                content: decode(content_str, Some(&msg_type))?,
+                buffers,
+                identities,
             })),
             "stream" => Ok(Response::IoPub(IoPubResponse::Stream {
                 header,
-                parent_header,
+                parent_header: parent_header?,
                 metadata,
-                content: serde_json::from_str(content_str)?,
+                content: decode(content_str, Some(&msg_type))?,
+                buffers,
+                identities,
             })),
             "error" => Ok(Response::IoPub(IoPubResponse::Error {
                 header,
-                parent_header,
+                parent_header: parent_header?,
                 metadata,
-                content: serde_json::from_str(content_str)?,
+                content: decode(content_str, Some(&msg_type))?,
+                buffers,
+                identities,
             })),
             "execute_result" => Ok(Response::IoPub(IoPubResponse::ExecuteResult {
                 header,
-                parent_header,
+                parent_header: parent_header?,
                 metadata,
-                content: serde_json::from_str(content_str)?,
+                content: decode(content_str, Some(&msg_type))?,
+                buffers,
+                identities,
             })),
             "clear_output" => Ok(Response::IoPub(IoPubResponse::ClearOutput {
                 header,
-                parent_header,
+                parent_header: parent_header?,
+                metadata,
+                content: decode(content_str, Some(&msg_type))?,
+                buffers,
+                identities,
+            })),
+            "display_data" => Ok(Response::IoPub(IoPubResponse::DisplayData {
+                // (@l-yc)
+                header,
+                parent_header: parent_header?,
+                metadata,
+                content: decode(content_str, Some(&msg_type))?,
+                buffers,
+                identities,
+            })),
+            "update_display_data" => Ok(Response::IoPub(IoPubResponse::UpdateDisplayData {
+                header,
+                parent_header: parent_header?,
+                metadata,
+                content: decode(content_str, Some(&msg_type))?,
+                buffers,
+                identities,
+            })),
+            "comm_open" => Ok(Response::IoPub(IoPubResponse::CommOpen {
+                header,
+                parent_header: parent_header?,
+                metadata,
+                content: decode(content_str, Some(&msg_type))?,
+                buffers,
+                identities,
+            })),
+            "comm_msg" => Ok(Response::IoPub(IoPubResponse::CommMsg {
+                header,
+                parent_header: parent_header?,
+                metadata,
+                content: decode(content_str, Some(&msg_type))?,
+                buffers,
+                identities,
+            })),
+            "comm_close" => Ok(Response::IoPub(IoPubResponse::CommClose {
+                header,
+                parent_header: parent_header?,
                 metadata,
-                content: serde_json::from_str(content_str)?,
+                content: decode(content_str, Some(&msg_type))?,
+                buffers,
+                identities,
             })),
-            "display_data" => Ok(Response::IoPub(IoPubResponse::DisplayData { /// (@l-yc)
+            "input_request" => Ok(Response::Stdin(StdinResponse::InputRequest {
                 header,
-                parent_header,
+                parent_header: parent_header?,
                 metadata,
-                content: serde_json::from_str(content_str)?,
+                content: decode(content_str, Some(&msg_type))?,
+                buffers,
+                identities,
             })),
-            _ => unreachable!("{}", header.msg_type),
+            _ => {
+                warn!("unknown message type `{}`", msg_type);
+                Ok(Response::Unknown {
+                    header,
+                    parent_header: parent_header.unwrap_or_else(|e| {
+                        warn!("cannot parse parent header of `{}`: {}", msg_type, e);
+                        None
+                    }),
+                    metadata: decode(metadata_str, Some(&msg_type))?,
+                    content: decode(content_str, Some(&msg_type))?,
+                    buffers,
+                    identities,
+                })
+            }
         }
     }
 
     pub(crate) fn into_packets(self) -> Result<Vec<Part>> {
-        let mut buf = Vec::with_capacity(4);
-
         // Start by adding the items that need a signature
-        buf.push(self.header);
-        buf.push(self.parent_header);
-        buf.push(self.metadata);
-        buf.push(self.content);
+        let buf = vec![self.header, self.parent_header, self.metadata, self.content];
 
-        let signature = sign(buf.as_slice(), self.auth.clone());
+        let signature = self.auth.signature(buf.as_slice());
 
-        let mut result = Vec::with_capacity(6);
+        let mut result = Vec::with_capacity(self.identities.len() + 6 + self.buffers.len());
+        result.extend(self.identities);
         result.push(DELIMITER.to_vec());
         result.push(signature.into_bytes());
         result.extend_from_slice(&buf);
+        result.extend(self.buffers);
 
         Ok(result)
     }
 }
 
+/// Read a frame as text.
+fn frame_str<'a>(frame: &'a [u8], name: &str) -> Result<&'a str> {
+    std::str::from_utf8(frame)
+        .map_err(|e| Error::MalformedFrames(format!("{} is not valid utf-8: {}", name, e)))
+}
+
+/// Decode JSON from a frame of a message with the given type.
+fn decode<T: DeserializeOwned>(s: &str, msg_type: Option<&str>) -> Result<T> {
+    serde_json::from_str(s).map_err(|source| Error::Json {
+        msg_type: msg_type.map(String::from),
+        source: Arc::new(source),
+    })
+}
+
+/// Parse a parent header, treating an empty object as no parent.
+fn parse_parent_header(raw: &Value, msg_type: &str) -> Result<Option<Header>> {
+    match raw {
+        Value::Object(map) if map.is_empty() => Ok(None),
+        _ => serde_json::from_value(raw.clone())
+            .map(Some)
+            .map_err(|source| Error::Json {
+                msg_type: Some(msg_type.to_string()),
+                source: Arc::new(source),
+            }),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::commands::Command;
+    use crate::header::Session;
     use crate::test_helpers::*;
     use serde_json::json;
 
@@ -287,10 +431,197 @@ mod tests {
         });
     }
 
+    #[test]
+    fn test_comm_open_into_packets() {
+        let cmd = Command::CommOpen {
+            comm_id: "u-u-i-d".to_string(),
+            target_name: "foobar".to_string(),
+            data: json!({"a": 1}),
+            buffers: Vec::new(),
+        };
+        assert_packets(PacketsTestData {
+            command: cmd,
+            expected_header_type: "comm_open",
+            expected_content: json!({
+                "comm_id": "u-u-i-d",
+                "target_name": "foobar",
+                "data": {"a": 1},
+            }),
+        });
+    }
+
+    #[test]
+    fn test_comm_msg_into_packets() {
+        let cmd = Command::CommMsg {
+            comm_id: "u-u-i-d".to_string(),
+            data: json!({"value": 10}),
+            buffers: Vec::new(),
+        };
+        assert_packets(PacketsTestData {
+            command: cmd,
+            expected_header_type: "comm_msg",
+            expected_content: json!({
+                "comm_id": "u-u-i-d",
+                "data": {"value": 10},
+            }),
+        });
+    }
+
+    #[test]
+    fn test_comm_close_into_packets() {
+        let cmd = Command::CommClose {
+            comm_id: "u-u-i-d".to_string(),
+            data: json!({}),
+            buffers: Vec::new(),
+        };
+        assert_packets(PacketsTestData {
+            command: cmd,
+            expected_header_type: "comm_close",
+            expected_content: json!({
+                "comm_id": "u-u-i-d",
+                "data": {},
+            }),
+        });
+    }
+
+    #[test]
+    fn test_interrupt_into_packets() {
+        let cmd = Command::Interrupt;
+        assert_packets(PacketsTestData {
+            command: cmd,
+            expected_header_type: "interrupt_request",
+            expected_content: json!({}),
+        });
+    }
+
+    #[test]
+    fn test_input_reply_into_packets() {
+        let cmd = Command::InputReply {
+            value: "hunter2".to_string(),
+        };
+        assert_packets(PacketsTestData {
+            command: cmd,
+            expected_header_type: "input_reply",
+            expected_content: json!({
+                "value": "hunter2",
+            }),
+        });
+    }
+
+    #[test]
+    fn test_buffers_sent_after_content() {
+        let cmd = Command::CommMsg {
+            comm_id: "u-u-i-d".to_string(),
+            data: json!({}),
+            buffers: vec![b"first".to_vec(), vec![0, 159, 146, 150]],
+        };
+        let packets = packets_from_command(cmd);
+        let mut packets = check_packet_preamble(packets, "comm_msg");
+
+        // Content, then the buffers untouched
+        let _content = packets.next().unwrap();
+        let packet = packets.next().unwrap();
+        compare_bytestrings!(&packet, b"first".as_ref());
+        assert_eq!(packets.next().unwrap(), vec![0, 159, 146, 150]);
+        assert!(packets.next().is_none());
+    }
+
+    #[test]
+    fn test_buffers_are_not_signed() {
+        use crate::signatures::Auth;
+
+        let auth = Auth::new("hmac-sha256", b"foobar").unwrap();
+        let frames = vec![
+            br#"{"date": "", "msg_id": "", "username": "", "session": "", "msg_type": "comm_msg", "version": ""}"#.to_vec(),
+            br#"{"date": "", "msg_id": "", "username": "", "session": "", "msg_type": "comm_msg", "version": ""}"#.to_vec(),
+            b"{}".to_vec(),
+            br#"{"comm_id": "u-u-i-d", "data": {}}"#.to_vec(),
+        ];
+        let signature = auth.signature(frames.as_slice());
+
+        let mut raw = vec![DELIMITER.to_vec(), signature.into_bytes()];
+        raw.extend(frames);
+        raw.push(vec![1, 2, 3]);
+        raw.push(vec![4, 5]);
+
+        let wire = WireMessage::from_raw_response(raw, auth).unwrap();
+        assert_eq!(wire.buffers, vec![vec![1, 2, 3], vec![4, 5]]);
+
+        match wire.into_response().unwrap() {
+            Response::IoPub(IoPubResponse::CommMsg { buffers, .. }) => {
+                assert_eq!(buffers, vec![vec![1, 2, 3], vec![4, 5]]);
+            }
+            _ => unreachable!("Incorrect response type, should be CommMsg"),
+        }
+    }
+
+    #[test]
+    fn test_identities_round_trip() {
+        use crate::signatures::Auth;
+
+        let auth = Auth::new("hmac-sha256", b"foobar").unwrap();
+        let frames = vec![
+            br#"{"date": "", "msg_id": "", "username": "", "session": "", "msg_type": "kernel_info_request", "version": ""}"#.to_vec(),
+            b"{}".to_vec(),
+            b"{}".to_vec(),
+            b"{}".to_vec(),
+        ];
+        let signature = auth.signature(frames.as_slice());
+
+        // Routers prefix binary identities, which need not be valid utf-8
+        let mut raw = vec![vec![0, 128, 0, 65, 167], b"proxy".to_vec()];
+        raw.push(DELIMITER.to_vec());
+        raw.push(signature.into_bytes());
+        raw.extend(frames);
+
+        let wire = WireMessage::from_raw_response(raw.clone(), auth).unwrap();
+        assert_eq!(
+            wire.identities,
+            vec![vec![0, 128, 0, 65, 167], b"proxy".to_vec()]
+        );
+        assert_eq!(wire.into_packets().unwrap(), raw);
+    }
+
+    #[test]
+    fn test_replayed_message_rejected() {
+        use crate::signatures::Auth;
+
+        let auth = Auth::new("hmac-sha256", b"foobar").unwrap();
+        let frames = vec![
+            br#"{"date": "", "msg_id": "", "username": "", "session": "", "msg_type": "status", "version": ""}"#.to_vec(),
+            b"{}".to_vec(),
+            b"{}".to_vec(),
+            br#"{"execution_state": "idle"}"#.to_vec(),
+        ];
+        let signature = auth.signature(frames.as_slice());
+        let mut raw = vec![DELIMITER.to_vec(), signature.into_bytes()];
+        raw.extend(frames);
+
+        assert!(WireMessage::from_raw_response(raw.clone(), auth.clone()).is_ok());
+        assert!(matches!(
+            WireMessage::from_raw_response(raw, auth),
+            Err(Error::DuplicateSignature { .. })
+        ));
+    }
+
+    #[test]
+    fn test_missing_frames() {
+        let auth = FakeAuth::create();
+        let raw = vec![
+            DELIMITER.to_vec(),
+            expected_signature().into_bytes(),
+            b"{}".to_vec(),
+        ];
+        assert!(matches!(
+            WireMessage::from_raw_response(raw, auth),
+            Err(Error::MalformedFrames(_))
+        ));
+    }
+
     fn packets_from_command(command: Command) -> impl Iterator<Item = Part> {
         let auth = FakeAuth::create();
         let wire = command
-            .into_wire(auth.clone())
+            .into_wire(auth.clone(), &Session::default())
             .expect("creating wire message");
         let packets = wire.into_packets().expect("creating packets");
         packets.into_iter()
@@ -343,5 +674,4 @@ mod tests {
         let content: Value = serde_json::from_str(content_str).unwrap();
         assert_eq!(content, testdata.expected_content);
     }
-
 }